@@ -1,8 +1,63 @@
-CREATE TABLE IF NOT EXISTS transaction(
+CREATE TABLE IF NOT EXISTS schema_version(
+  version INTEGER PRIMARY KEY,
+  applied_at INTEGER NOT NULL
+);
+
+CREATE TABLE IF NOT EXISTS `transaction`(
   id INTEGER PRIMARY KEY AUTOINCREMENT,
   created_at INTEGER NOT NULL,
+  kind TEXT NOT NULL DEFAULT 'income',
   tax INTEGER NOT NULL,
-  category TEXT NOT NULL,
+  category_id INTEGER REFERENCES category(id),
   duration INTEGER DEFAULT 0,
-  description TEXT
+  description TEXT,
+  currency TEXT NOT NULL DEFAULT 'USD',
+  account_id INTEGER REFERENCES account(id),
+  deleted_at INTEGER,
+  batch INTEGER
+);
+
+CREATE TABLE IF NOT EXISTS category(
+  id INTEGER PRIMARY KEY AUTOINCREMENT,
+  name TEXT NOT NULL UNIQUE
+);
+
+CREATE TABLE IF NOT EXISTS account(
+  id INTEGER PRIMARY KEY AUTOINCREMENT,
+  name TEXT NOT NULL UNIQUE
+);
+
+INSERT OR IGNORE INTO account (name) VALUES ('default');
+
+CREATE TABLE IF NOT EXISTS transfer(
+  id INTEGER PRIMARY KEY AUTOINCREMENT,
+  created_at INTEGER NOT NULL,
+  tax INTEGER NOT NULL,
+  source TEXT NOT NULL,
+  target TEXT NOT NULL,
+  batch INTEGER
+);
+
+CREATE TABLE IF NOT EXISTS tag(
+  id INTEGER PRIMARY KEY AUTOINCREMENT,
+  name TEXT NOT NULL UNIQUE
+);
+
+CREATE TABLE IF NOT EXISTS transaction_tag(
+  transaction_id INTEGER NOT NULL REFERENCES `transaction`(id) ON DELETE CASCADE,
+  tag_id INTEGER NOT NULL REFERENCES tag(id),
+  PRIMARY KEY (transaction_id, tag_id)
+);
+
+CREATE TABLE IF NOT EXISTS budget(
+  category TEXT PRIMARY KEY,
+  monthly_limit INTEGER NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS transaction_created_at ON `transaction`(created_at);
+CREATE INDEX IF NOT EXISTS transaction_category ON `transaction`(category_id);
+
+CREATE TABLE IF NOT EXISTS shorthand(
+  name TEXT PRIMARY KEY,
+  template TEXT NOT NULL
 );
@@ -0,0 +1,4757 @@
+use chrono::prelude::*;
+use std::str::FromStr;
+use std::fmt;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::io::IsTerminal;
+use directories::ProjectDirs;
+use rusqlite::{params, Connection, OpenFlags, OptionalExtension, Result, TransactionBehavior};
+use rusqlite::types::{FromSql, FromSqlError, FromSqlResult, Value, ValueRef};
+use serde::Serialize;
+use unicode_segmentation::UnicodeSegmentation;
+use error::*;
+
+pub mod error {
+    use super::*;
+
+    /// A command that can't be parsed. `pos` is the character offset of
+    /// `token` within the command.
+    #[derive(Debug)]
+    pub struct ParseCmdError {
+        pub pos: usize,
+        pub token: String,
+        pub kind: ParseErrorKind,
+    }
+    #[derive(Debug, Clone, Copy, PartialEq)]
+    pub enum ParseErrorKind {
+        Empty,
+        UnexpectedMod,
+        NoFields,
+        BadAmount,
+        BadExpression,
+        AmountOutOfRange,
+        BadDuration,
+        BadDate,
+        BadRate,
+        BadCurrency,
+        UnterminatedQuote,
+        AfterQuote,
+        BadShorthand,
+        /// Counting from 1 after the modifier.
+        EmptyField(usize),
+        TwoDates,
+        TwoAccounts,
+        TransferAccount,
+    }
+    /// Any error a linda command can fail with.
+    #[derive(Debug)]
+    pub enum LindaError {
+        Parse(ParseCmdError),
+        UnknownOrderKind(char),
+        Invalid(InvalidCmdError),
+        Batch(BatchError),
+        Unsupported(String),
+        NotFound(i64),
+        NotInitialized(PathBuf),
+        NotDeleted(i64),
+        UnknownCategory(String),
+        BadTimestamp(i64, String),
+        NothingToChange,
+        NothingToUndo,
+        UnknownAccount(String),
+        BadBackup(PathBuf, String),
+        Corrupt(String),
+        OutdatedSchema(usize),
+        NewerSchema(usize),
+        BadShorthand(String, String),
+        Import(ImportError),
+        Database(rusqlite::Error),
+        Io(std::io::Error),
+        Csv(csv::Error),
+        Json(serde_json::Error),
+    }
+    #[derive(Debug)]
+    pub struct BadSepError(pub char, pub &'static str);
+    #[derive(Debug)]
+    pub struct BatchError {
+        pub index: usize,
+        /// Of the input, when it was read line by line.
+        pub line: Option<usize>,
+        pub text: String,
+        pub reason: String,
+    }
+    /// A part of a command that isn't of the type it was read as.
+    #[derive(Debug)]
+    pub struct KindMismatch {
+        pub expected: &'static str,
+        pub found: String,
+    }
+    #[derive(Debug)]
+    pub enum ImportError {
+        Header(String),
+        Row(u64, String),
+    }
+    #[derive(Debug)]
+    pub enum InvalidCmdError {
+        /// The field, and the shape of the command it is missing from.
+        Missing(Field, &'static str),
+        Mismatch(Field, String),
+        SameAccount(String),
+        InvalidAmount(i64),
+        Unexpected(usize, String, &'static str),
+        RateNotIncome(char),
+    }
+
+    impl ParseCmdError {
+        pub fn new(kind: ParseErrorKind, token: &str) -> ParseCmdError {
+            ParseCmdError { pos: 0, token: String::from(token), kind }
+        }
+        /// Moves the error by `offset`, for errors found in a part of the command.
+        pub fn at(mut self, offset: usize) -> ParseCmdError {
+            self.pos += offset;
+            self
+        }
+        /// The error without its position, for values given outside a command.
+        pub fn describe(&self) -> String {
+            format!("'{}': {}", self.token, self.kind)
+        }
+    }
+
+    impl fmt::Display for ParseCmdError {
+        fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            match self.kind {
+                ParseErrorKind::Empty => write!(f, "{}", self.kind),
+                ParseErrorKind::UnexpectedMod => {
+                    write!(f, "unknown command type '{}' at position {}", self.token, self.pos)?;
+                    if let Some(m) = self.token.chars().next().and_then(closest_mod) {
+                        write!(f, ", did you mean '{}' ({})?", m, mod_meaning(m))?;
+                    } else {
+                        write!(f, ".")?;
+                    }
+                    write!(f, " {}", self.kind)
+                },
+                _ if self.token.is_empty() => write!(f, "{} at position {}", self.kind, self.pos),
+                _ => write!(f, "unexpected token '{}' at position {}: {}", self.token, self.pos, self.kind),
+            }
+        }
+    }
+
+    impl fmt::Display for ParseErrorKind {
+        fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            match self {
+                ParseErrorKind::Empty => write!(f, "the command line is empty"),
+                ParseErrorKind::UnexpectedMod => write!(
+                    f, "Modifiers: {}", MODS.iter().map(|m| format!("{} {}", m, mod_meaning(*m))).collect::<Vec<_>>().join(", ")
+                ),
+                ParseErrorKind::NoFields => write!(f, "expected fields after the modifier"),
+                ParseErrorKind::BadAmount => write!(f, "expected an amount like 12, 12.50 or 1.2k"),
+                ParseErrorKind::BadExpression => write!(f, "expected an amount expression like 12+3.50"),
+                ParseErrorKind::AmountOutOfRange => write!(f, "the amount is out of range"),
+                ParseErrorKind::BadDuration => write!(f, "expected a duration in whole minutes like ~90"),
+                ParseErrorKind::BadDate => write!(f, "expected a date like @YYYY-MM-DD, @today, @yesterday, @-Nd, @-Nw or a weekday"),
+                ParseErrorKind::BadRate => write!(f, "expected a percentage from 0 to 100 like %13"),
+                ParseErrorKind::BadCurrency => write!(f, "expected a three letter currency code like EUR"),
+                ParseErrorKind::UnterminatedQuote => write!(f, "the quote is never closed"),
+                ParseErrorKind::AfterQuote => write!(f, "expected a separator after the closing quote"),
+                ParseErrorKind::BadShorthand => write!(f, "the shorthand doesn't expand to a command starting with a modifier"),
+                ParseErrorKind::EmptyField(n) => write!(f, "field {} is empty", n),
+                ParseErrorKind::TwoDates => write!(f, "a command may only have one @date"),
+                ParseErrorKind::TwoAccounts => write!(f, "a command may only have one @account"),
+                ParseErrorKind::TransferAccount => write!(f, "a transfer names its accounts as source and target, not with @"),
+            }
+        }
+    }
+
+    impl fmt::Display for LindaError {
+        fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            match self {
+                LindaError::Parse(e) => write!(f, "{}", e),
+                LindaError::UnknownOrderKind(ch) => write!(f, "'{}' is not an order modifier, expected &, > or -", ch),
+                LindaError::Invalid(e) => write!(f, "{}", e),
+                LindaError::Batch(e) => write!(f, "{}", e),
+                LindaError::Unsupported(shape) => write!(f, "Unrecognized command shape: {}", shape),
+                LindaError::NotFound(id) => write!(f, "No transaction #{}", id),
+                LindaError::NotDeleted(id) => write!(f, "Transaction #{} isn't deleted", id),
+                LindaError::NotInitialized(db) => write!(f, "There is no linda database at {}, run `linda init` first", db.display()),
+                LindaError::UnknownCategory(name) => write!(f, "There is no category named '{}'", name),
+                LindaError::BadTimestamp(id, text) => write!(
+                    f, "Transaction #{} was created at '{}', which is not a Unix timestamp", id, text
+                ),
+                LindaError::NothingToChange => write!(f, "Nothing was changed, specify at least one field to edit"),
+                LindaError::NothingToUndo => write!(f, "There is no transaction to undo"),
+                LindaError::UnknownAccount(name) => write!(
+                    f, "There is no account named '{}', add it with `linda accounts add` or pass --new-account", name
+                ),
+                LindaError::BadBackup(path, reason) => write!(f, "Can't restore {}: {}", path.display(), reason),
+                LindaError::Corrupt(problems) => write!(
+                    f, "The integrity check failed: {}. Put a backup in place with `linda restore-backup <file>`", problems
+                ),
+                LindaError::OutdatedSchema(version) => write!(
+                    f, "The database is at schema version {} and needs to be at {}, run `linda init` to upgrade it",
+                    version, SCHEMA_VERSION
+                ),
+                LindaError::NewerSchema(version) => write!(
+                    f, "The database is at schema version {}, newer than the {} this linda knows, upgrade linda to use it",
+                    version, SCHEMA_VERSION
+                ),
+                LindaError::BadShorthand(name, reason) => write!(f, "Can't define shorthand '{}': {}", name, reason),
+                LindaError::Import(e) => write!(f, "{}", e),
+                LindaError::Database(e) => write!(f, "Database error: {}", e),
+                LindaError::Io(e) => write!(f, "{}", e),
+                LindaError::Csv(e) => write!(f, "CSV error: {}", e),
+                LindaError::Json(e) => write!(f, "JSON error: {}", e),
+            }
+        }
+    }
+
+    impl std::error::Error for LindaError {
+        fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+            match self {
+                LindaError::Database(e) => Some(e),
+                LindaError::Io(e) => Some(e),
+                LindaError::Csv(e) => Some(e),
+                LindaError::Json(e) => Some(e),
+                _ => None,
+            }
+        }
+    }
+
+    impl From<ParseCmdError> for LindaError {
+        fn from(e: ParseCmdError) -> Self { LindaError::Parse(e) }
+    }
+
+    impl From<InvalidCmdError> for LindaError {
+        fn from(e: InvalidCmdError) -> Self { LindaError::Invalid(e) }
+    }
+
+    impl From<BatchError> for LindaError {
+        fn from(e: BatchError) -> Self { LindaError::Batch(e) }
+    }
+
+    impl From<ImportError> for LindaError {
+        fn from(e: ImportError) -> Self { LindaError::Import(e) }
+    }
+
+    impl From<rusqlite::Error> for LindaError {
+        fn from(e: rusqlite::Error) -> Self { LindaError::Database(e) }
+    }
+
+    impl From<std::io::Error> for LindaError {
+        fn from(e: std::io::Error) -> Self { LindaError::Io(e) }
+    }
+
+    impl From<csv::Error> for LindaError {
+        fn from(e: csv::Error) -> Self { LindaError::Csv(e) }
+    }
+
+    impl From<serde_json::Error> for LindaError {
+        fn from(e: serde_json::Error) -> Self { LindaError::Json(e) }
+    }
+
+    impl fmt::Display for BadSepError {
+        fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            write!(f, "'{}' can't be used as a field separator: {}", self.0, self.1)
+        }
+    }
+
+    impl fmt::Display for BatchError {
+        fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            match self.line {
+                Some(line) => write!(f, "Line {} '{}' failed: {}", line, self.text, self.reason),
+                None => write!(f, "Command {} '{}' failed: {}", self.index, self.text, self.reason),
+            }
+        }
+    }
+
+    impl fmt::Display for KindMismatch {
+        fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            write!(f, "expected {}, found '{}'", self.expected, self.found)
+        }
+    }
+
+    impl std::error::Error for KindMismatch {}
+
+    impl fmt::Display for ImportError {
+        fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            match self {
+                ImportError::Header(got) => write!(f, "Expected the header '{}', got '{}'", CSV_HEADER.join(","), got),
+                ImportError::Row(line, reason) => write!(f, "Line {}: {}", line, reason),
+            }
+        }
+    }
+
+    impl fmt::Display for InvalidCmdError {
+        fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            match self {
+                InvalidCmdError::Missing(field, shape) => write!(f, "missing {}, expected {}", field, shape),
+                InvalidCmdError::Mismatch(field, found) => {
+                    let article = if matches!(field, Field::Amount) { "an" } else { "a" };
+                    write!(f, "expected {} {}, found '{}'", article, field, found)
+                },
+                InvalidCmdError::SameAccount(name) => write!(f, "a transfer needs two different accounts, not '{}' twice", name),
+                InvalidCmdError::InvalidAmount(a) => write!(
+                    f, "the amount must be positive, got {}; the sign is given by the modifier", format_cents(*a)
+                ),
+                InvalidCmdError::Unexpected(pos, field, shape) => write!(
+                    f, "unexpected field '{}' (position {}), expected {}", field, pos, shape
+                ),
+                InvalidCmdError::RateNotIncome(ch) => write!(f, "a %rate share can only be taken from income, not '{}'", ch),
+            }
+        }
+    }
+}
+
+pub const MODS: [char; 4] = ['&', '>', '+', '-'];
+pub const SEP: char = ',';
+pub const CMD_SEP: char = ';';
+pub const ORDER_SHAPE: &str = "<mod><amount>,<category> and at most one description, ~minutes and %rate";
+pub const TRANSFER_SHAPE: &str = "+<amount>,<source>,<target>";
+pub const DATABASE_FILENAME: &str = "linda.db";
+/// Characters that mean something inside a field, so they can't separate
+/// fields.
+const FIELD_CHARS: &[char] = &['"', '\\', '~', '@', '.', '-', '*', '/', '#', '%'];
+
+/// Where the database lives unless told otherwise: `linda.db` in linda's
+/// data directory, `$XDG_DATA_HOME/linda` or `~/.local/share/linda` on Linux.
+/// `None` if there's no home directory to go by.
+pub fn data_db() -> Option<PathBuf> {
+    ProjectDirs::from("", "", "linda").map(|dirs| dirs.data_dir().join(DATABASE_FILENAME))
+}
+
+/// The database to use when none is given: `data_db`, unless a `linda.db`
+/// is left in the current directory from before that default, which is still
+/// used but with a warning.
+pub fn default_db() -> (PathBuf, Option<String>) {
+    let legacy = PathBuf::from(DATABASE_FILENAME);
+    match data_db() {
+        Some(data) if !legacy.exists() => (data, None),
+        Some(data) => {
+            let warning = format!(
+                "using {} in the current directory, move it to {} or pass --db to keep using it",
+                DATABASE_FILENAME, data.display(),
+            );
+            (legacy, Some(warning))
+        },
+        None => (legacy, None),
+    }
+}
+
+/// The database to use: `given`, else `default_db`, made absolute so that
+/// what is printed is where the data is. `:memory:` is left as it is.
+pub fn resolve_db(given: Option<PathBuf>) -> std::io::Result<(PathBuf, Option<String>)> {
+    let (db, warning) = match given {
+        Some(db) => (db, None),
+        None => default_db(),
+    };
+    if db.as_os_str() == MEMORY_DB {
+        return Ok((db, warning));
+    }
+    Ok((std::path::absolute(db)?, warning))
+}
+/// Account of transactions that don't name one.
+pub const DEFAULT_ACCOUNT: &str = "default";
+
+/// What a modifier stands for, for messages.
+pub fn mod_meaning(m: char) -> &'static str {
+    match m {
+        '&' => "income",
+        '>' => "expense",
+        '+' => "transfer",
+        '-' => "refund",
+        _ => "unknown",
+    }
+}
+
+/// The first user-perceived character of `text`, so that `é`, `👍🏽` or a flag
+/// is echoed whole in errors.
+fn first_grapheme(text: &str) -> &str {
+    text.graphemes(true).next().unwrap_or("")
+}
+
+/// The modifier a verbose command spells out, as in `income 100 salary`.
+fn mod_of_word(word: &str) -> Option<char> {
+    let word = word.to_lowercase();
+    MODS.iter().copied().find(|m| mod_meaning(*m) == word)
+}
+
+/// Guesses the modifier meant by a character that isn't one: the other side
+/// of the same key or a look-alike, otherwise an expense as the most common
+/// entry. Letters, digits and spaces get no guess.
+fn closest_mod(ch: char) -> Option<char> {
+    match ch {
+        '<' | '.' | ',' => Some('>'),
+        '=' => Some('+'),
+        '_' | '\u{2013}' | '\u{2014}' | '\u{2212}' => Some('-'),
+        c if c.is_alphanumeric() || c.is_whitespace() => None,
+        _ => Some('>'),
+    }
+}
+/// Currency of amounts written without a code, unless configured otherwise.
+pub const BASE_CURRENCY: &str = "USD";
+
+#[derive(Debug, Clone, Copy, clap::ValueEnum)]
+pub enum Format {
+    Text,
+    Json,
+}
+
+#[derive(Debug, Clone, Copy, clap::ValueEnum)]
+pub enum ExportFormat {
+    Csv,
+}
+
+pub const CSV_HEADER: [&str; 9] = [
+    "id", "created_at", "tax", "category", "duration", "description", "kind", "currency", "account",
+];
+
+/// Settings that change how a command line is split into fields.
+#[derive(Debug, Clone)]
+pub struct Syntax {
+    pub sep: char,
+    /// Trim, lowercase and collapse inner whitespace of categories.
+    pub normalize: bool,
+    /// Templates by name, see `shorthand`.
+    pub shorthands: HashMap<String, String>,
+}
+
+impl Default for Syntax {
+    fn default() -> Self { Syntax { sep: SEP, normalize: true, shorthands: HashMap::new() } }
+}
+
+impl Syntax {
+    pub fn category(&self, text: &str) -> String {
+        if self.normalize {
+            text.split_whitespace().collect::<Vec<_>>().join(" ").to_lowercase()
+        } else {
+            String::from(text)
+        }
+    }
+    pub fn with_sep(sep: char) -> Result<Syntax, BadSepError> {
+        let reason = if sep.is_alphanumeric() || sep.is_whitespace() {
+            "letters, digits and spaces are part of the fields"
+        } else if MODS.contains(&sep) {
+            "it is a command modifier"
+        } else if sep == CMD_SEP {
+            "it separates commands"
+        } else if FIELD_CHARS.contains(&sep) {
+            "it already has a meaning inside fields"
+        } else {
+            return Ok(Syntax { sep, ..Syntax::default() });
+        };
+        Err(BadSepError(sep, reason))
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Cmd {
+    pub pack: Vec<PartOfCmdKind>,
+    pub created_at: DateTime<Local>,
+    pub tags: Vec<String>,
+    /// Currency code written after the amount, like `EUR` in `>45EUR,hotel`.
+    pub currency: Option<String>,
+    /// Account the command books to, `DEFAULT_ACCOUNT` when it's not given.
+    pub account: Option<String>,
+    /// What the pack means, `None` when its shape isn't a known command.
+    pub op: Option<Op>,
+}
+
+/// A command read into typed fields.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Op {
+    Order(Order),
+    Transfer { amount: i64, source: String, target: String },
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Order {
+    pub kind: OrderKind,
+    /// Amount in cents.
+    pub amount: i64,
+    pub category: String,
+    pub description: Option<String>,
+    /// Duration in minutes.
+    pub duration: i32,
+    /// Share of the amount that is booked as a separate `tax` expense, in
+    /// hundredths of a percent.
+    pub rate: Option<i64>,
+    /// `None` for the base currency.
+    pub currency: Option<String>,
+}
+
+#[derive(Debug, Clone)]
+pub enum PartOfCmdKind {
+    Mod(char),
+    /// Amount in cents.
+    Digit(i64),
+    Word(String),
+    /// Duration in minutes, written as `~90`.
+    Duration(i32),
+    /// Percentage in hundredths, written as `%13`.
+    Rate(i64),
+}
+
+impl TryFrom<&PartOfCmdKind> for i64 {
+    type Error = KindMismatch;
+
+    fn try_from(part: &PartOfCmdKind) -> Result<i64, KindMismatch> {
+        match part {
+            PartOfCmdKind::Digit(d) => Ok(*d),
+            _ => Err(KindMismatch { expected: "an amount", found: part.to_string() }),
+        }
+    }
+}
+
+impl TryFrom<&PartOfCmdKind> for i32 {
+    type Error = KindMismatch;
+
+    fn try_from(part: &PartOfCmdKind) -> Result<i32, KindMismatch> {
+        match part {
+            PartOfCmdKind::Duration(m) => Ok(*m),
+            _ => Err(KindMismatch { expected: "a duration", found: part.to_string() }),
+        }
+    }
+}
+
+impl TryFrom<&PartOfCmdKind> for String {
+    type Error = KindMismatch;
+
+    fn try_from(part: &PartOfCmdKind) -> Result<String, KindMismatch> {
+        match part {
+            PartOfCmdKind::Word(w) => Ok(w.clone()),
+            _ => Err(KindMismatch { expected: "a word", found: part.to_string() }),
+        }
+    }
+}
+
+impl PartOfCmdKind {
+    pub fn describe(&self) -> String {
+        match self {
+            PartOfCmdKind::Mod(ch) => format!("modifier '{}'", ch),
+            PartOfCmdKind::Digit(d) => format!("amount {}", format_cents(*d)),
+            PartOfCmdKind::Word(w) => format!("word '{}'", w),
+            PartOfCmdKind::Duration(m) => format!("duration ~{}", m),
+            PartOfCmdKind::Rate(r) => format!("rate %{}", compact_cents(*r)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, strum_macros::Display)]
+#[strum(serialize_all = "lowercase")]
+pub enum Field {
+    Amount,
+    Category,
+    Source,
+    Target,
+}
+
+impl Field {
+    fn accepts(&self, part: &PartOfCmdKind) -> bool {
+        match self {
+            Field::Amount => i64::try_from(part).is_ok(),
+            Field::Category | Field::Source | Field::Target => String::try_from(part).is_ok(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, strum_macros::Display)]
+#[serde(rename_all = "lowercase")]
+#[strum(serialize_all = "lowercase")]
+pub enum OrderKind {
+    Income,
+    Expense,
+    /// Money back for an expense, counted against expenses in reports.
+    Refund,
+}
+
+#[derive(Debug)]
+pub enum CmdKind {
+    Order(OrderKind),
+    Transfer,
+}
+
+impl fmt::Display for PartOfCmdKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            PartOfCmdKind::Mod(ch) => write!(f, "{}", ch),
+            PartOfCmdKind::Digit(d) => write!(f, "{}", compact_cents(*d)),
+            PartOfCmdKind::Word(w) if needs_quotes(w) => write!(f, "\"{}\"", w.replace('"', "\"\"")),
+            PartOfCmdKind::Word(w) => write!(f, "{}", w),
+            PartOfCmdKind::Duration(m) => write!(f, "~{}", m),
+            PartOfCmdKind::Rate(r) => write!(f, "%{}", compact_cents(*r)),
+        }
+    }
+}
+
+/// Whether a word would be read back as something else if written bare.
+fn needs_quotes(w: &str) -> bool {
+    w.is_empty()
+        || w.trim() != w
+        || w.contains([SEP, CMD_SEP, '"'])
+        || w.starts_with(|c: char| c.is_ascii_digit() || ['.', '-', '~', '@', '%'].contains(&c))
+        || w.split_whitespace().any(|w| w.starts_with('#'))
+}
+
+/// Formats a command back into its canonical syntax, so that parsing the
+/// result gives the same pack.
+impl fmt::Display for Cmd {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut parts = self.pack.iter();
+        if let Some(m) = parts.next() {
+            write!(f, "{}", m)?;
+        }
+        let mut fields: Vec<String> = parts.map(|p| p.to_string()).collect();
+        if let (Some(code), Some(amount)) = (&self.currency, fields.first_mut()) {
+            amount.push_str(code);
+        }
+        if !self.tags.is_empty() {
+            fields.push(self.tags.iter().map(|t| format!("#{}", t)).collect::<Vec<_>>().join(" "));
+        }
+        write!(f, "{}", fields.join(&SEP.to_string()))
+    }
+}
+
+impl OrderKind {
+    pub fn new(ch: char) -> Result<OrderKind, LindaError> {
+        match ch {
+            '&' => Ok(OrderKind::Income),
+            '>' => Ok(OrderKind::Expense),
+            '-' => Ok(OrderKind::Refund),
+            _ => Err(LindaError::UnknownOrderKind(ch))
+        }
+    }
+    pub fn from_name(name: &str) -> Option<OrderKind> {
+        match name {
+            "income" => Some(OrderKind::Income),
+            "expense" => Some(OrderKind::Expense),
+            "refund" => Some(OrderKind::Refund),
+            _ => None,
+        }
+    }
+    pub fn modifier(self) -> char {
+        match self {
+            OrderKind::Income => '&',
+            OrderKind::Expense => '>',
+            OrderKind::Refund => '-',
+        }
+    }
+}
+
+impl FromSql for OrderKind {
+    fn column_result(value: ValueRef) -> FromSqlResult<Self> {
+        let name = value.as_str()?;
+        OrderKind::from_name(name)
+            .ok_or_else(|| FromSqlError::Other(format!("unknown order kind '{}'", name).into()))
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct Tr {
+    pub created_at: DateTime<Local>,
+    pub kind: OrderKind,
+    pub tax: i64,
+    pub category: String,
+    pub description: Option<String>,
+    pub duration: i32,
+    pub tags: Vec<String>,
+    pub currency: String,
+    pub account: String,
+}
+
+impl Tr {
+    /// Like `expense #42: 250.00 USD groceries, weekly shop #food (2024-05-03)`.
+    pub fn describe(&self, id: i64) -> String {
+        format!(
+            "{} #{}: {} {} {}{}{} ({})", self.kind, id, format_cents(self.tax), self.currency, self.category,
+            self.description.as_deref().map(|d| format!(", {}", d)).unwrap_or_default(),
+            self.tags.iter().map(|t| format!(" #{}", t)).collect::<String>(),
+            self.created_at.format("%Y-%m-%d")
+        )
+    }
+    /// The command that would record this transaction again, on the same day
+    /// and to the same account unless its name can't be an `@account` field.
+    pub fn to_cmd(&self) -> String {
+        let mut fields = vec![
+            format!("{}{}{}", self.kind.modifier(), PartOfCmdKind::Digit(self.tax), self.currency),
+            PartOfCmdKind::Word(self.category.clone()).to_string(),
+        ];
+        if self.duration != 0 {
+            fields.push(PartOfCmdKind::Duration(self.duration).to_string());
+        }
+        if let Some(description) = &self.description {
+            fields.push(PartOfCmdKind::Word(description.clone()).to_string());
+        }
+        if !self.tags.is_empty() {
+            fields.push(self.tags.iter().map(|t| format!("#{}", t)).collect::<Vec<_>>().join(" "));
+        }
+        fields.push(self.created_at.format("@%Y-%m-%d").to_string());
+        if self.account != DEFAULT_ACCOUNT && is_account_name(&self.account) {
+            fields.push(format!("@{}", self.account));
+        }
+        fields.join(&SEP.to_string())
+    }
+    /// Reads an order, in `base` currency unless it names another one.
+    pub fn new(cmd: Cmd, base: &str) -> Result<Tr, LindaError> {
+        match cmd.op {
+            Some(Op::Order(order)) =>
+                Ok(Tr {
+                    created_at: cmd.created_at,
+                    kind: order.kind,
+                    tax: order.amount,
+                    category: order.category,
+                    description: order.description,
+                    duration: order.duration,
+                    tags: cmd.tags,
+                    currency: order.currency.unwrap_or_else(|| String::from(base)),
+                    account: cmd.account.unwrap_or_else(|| String::from(DEFAULT_ACCOUNT)),
+                }),
+            _ => Err(LindaError::Unsupported(cmd.shape())),
+        }
+    }
+    const INSERT: &'static str =
+        "INSERT INTO `transaction` (created_at, kind, tax, category_id, description, duration, currency, account_id)
+         VALUES (?1, ?2, ?3, (SELECT id FROM category WHERE name = ?4), ?5, ?6, ?7, (SELECT id FROM account WHERE name = ?8))";
+    const INSERT_CATEGORY: &'static str = "INSERT OR IGNORE INTO category (name) VALUES (?1)";
+    const INSERT_ACCOUNT: &'static str = "INSERT OR IGNORE INTO account (name) VALUES (?1)";
+    const INSERT_TAG: &'static str = "INSERT OR IGNORE INTO tag (name) VALUES (?1)";
+    const LINK_TAG: &'static str =
+        "INSERT OR IGNORE INTO transaction_tag (transaction_id, tag_id) SELECT ?1, id FROM tag WHERE name = ?2";
+
+    fn values(&self) -> Vec<Value> {
+        vec![
+            Value::Integer(self.created_at.timestamp()),
+            Value::Text(self.kind.to_string()),
+            Value::Integer(self.tax),
+            Value::Text(self.category.clone()),
+            self.description.clone().map_or(Value::Null, Value::Text),
+            Value::Integer(self.duration.into()),
+            Value::Text(self.currency.clone()),
+            Value::Text(self.account.clone()),
+        ]
+    }
+    /// Inserts the transaction along with its tags and returns its id. Its
+    /// category and account are added if they are new.
+    pub fn insert(&self, conn: &Connection) -> Result<i64> {
+        conn.execute(Tr::INSERT_CATEGORY, [&self.category])?;
+        conn.execute(Tr::INSERT_ACCOUNT, [&self.account])?;
+        conn.execute(Tr::INSERT, rusqlite::params_from_iter(self.values()))?;
+        let id = conn.last_insert_rowid();
+        for tag in &self.tags {
+            conn.execute(Tr::INSERT_TAG, [tag])?;
+            conn.execute(Tr::LINK_TAG, params![id, tag])?;
+        }
+        Ok(id)
+    }
+    /// The statements `insert` would run, with their values filled in.
+    pub fn sql(&self) -> Vec<String> {
+        let mut sql = vec![
+            show_sql(Tr::INSERT_CATEGORY, &[Value::Text(self.category.clone())]),
+            show_sql(Tr::INSERT_ACCOUNT, &[Value::Text(self.account.clone())]),
+            show_sql(Tr::INSERT, &self.values()),
+        ];
+        for tag in &self.tags {
+            sql.push(show_sql(Tr::INSERT_TAG, &[Value::Text(tag.clone())]));
+            sql.push(show_sql(Tr::LINK_TAG, &[Value::Text(String::from("<new id>")), Value::Text(tag.clone())]));
+        }
+        sql
+    }
+    /// Reads a row in the export layout. The id is ignored, imported rows get new ones.
+    pub fn from_csv(row: &csv::StringRecord, syntax: &Syntax) -> Result<Tr, String> {
+        let created_at = DateTime::parse_from_rfc3339(&row[1])
+            .map_err(|_| format!("'{}' is not an RFC 3339 timestamp", &row[1]))?;
+        let tax = parse_number(&row[2]).map_err(|e| e.describe())?;
+        if tax <= 0 {
+            return Err(InvalidCmdError::InvalidAmount(tax).to_string());
+        }
+        if row[3].trim().is_empty() {
+            return Err(String::from("the category is empty"));
+        }
+        Ok(Tr {
+            created_at: created_at.with_timezone(&Local),
+            kind: OrderKind::from_name(&row[6]).ok_or_else(|| format!("'{}' is not income, expense or refund", &row[6]))?,
+            tax,
+            category: syntax.category(&row[3]),
+            description: Some(row[5].to_string()).filter(|d| !d.is_empty()),
+            duration: row[4].parse().map_err(|_| format!("'{}' is not a duration in minutes", &row[4]))?,
+            tags: Vec::new(),
+            currency: parse_currency(&row[7]).map_err(|e| e.describe())?,
+            account: row.get(8).map(str::trim).filter(|a| !a.is_empty()).unwrap_or(DEFAULT_ACCOUNT).to_string(),
+        })
+    }
+    pub fn from_row(row: &rusqlite::Row) -> Result<Tr> {
+        let secs: i64 = row.get("created_at")?;
+        Ok(Tr {
+            created_at: from_timestamp(secs).ok_or(rusqlite::Error::IntegralValueOutOfRange(0, secs))?,
+            kind: row.get("kind")?,
+            tax: row.get("tax")?,
+            category: row.get("category")?,
+            description: row.get("description")?,
+            duration: row.get("duration")?,
+            tags: row.get::<_, Option<String>>("tags")?
+                .map(|t| t.split(' ').map(String::from).collect())
+                .unwrap_or_default(),
+            currency: row.get("currency")?,
+            account: row.get("account")?,
+        })
+    }
+}
+
+/// A transaction as stored in the database.
+#[derive(Debug, Serialize)]
+pub struct Record {
+    pub id: i64,
+    #[serde(flatten)]
+    pub tr: Tr,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub deleted_at: Option<DateTime<Local>>,
+}
+
+impl Record {
+    const SELECT: &'static str = "SELECT id, created_at, kind, tax, description, duration, currency, deleted_at,
+            (SELECT name FROM category WHERE category.id = category_id) AS category,
+            (SELECT name FROM account WHERE account.id = account_id) AS account,
+            (SELECT GROUP_CONCAT(name, ' ') FROM tag JOIN transaction_tag ON tag.id = tag_id
+             WHERE transaction_id = t.id) AS tags
+         FROM `transaction` t";
+
+    pub fn query<P: rusqlite::Params>(conn: &Connection, clause: &str, params: P) -> Result<Vec<Record>> {
+        let mut stmt = conn.prepare(&format!("{} {}", Record::SELECT, clause))?;
+        let records = stmt.query_map(params, |row| Ok(Record {
+            id: row.get("id")?,
+            tr: Tr::from_row(row)?,
+            deleted_at: row.get::<_, Option<i64>>("deleted_at")?.and_then(from_timestamp),
+        }))?;
+        records.collect()
+    }
+    /// The latest `limit` records within `period`, newest first, of one
+    /// account or of all of them. Deleted ones are left out unless
+    /// `include_deleted`.
+    pub fn select(conn: &Connection, period: Period, account: Option<&str>, limit: u32, include_deleted: bool) -> Result<Vec<Record>> {
+        Record::query(
+            conn,
+            "WHERE created_at >= ?1 AND created_at < ?2 AND (?3 IS NULL OR account_id = (SELECT id FROM account WHERE name = ?3))
+               AND (?5 OR deleted_at IS NULL)
+             ORDER BY created_at DESC, id DESC LIMIT ?4",
+            params![period.since, period.until, account, limit, include_deleted],
+        )
+    }
+    /// Every record that isn't deleted.
+    pub fn all(conn: &Connection) -> Result<Vec<Record>> {
+        Record::query(conn, "WHERE deleted_at IS NULL ORDER BY id", [])
+    }
+}
+
+#[derive(Debug)]
+pub struct Transfer {
+    created_at: DateTime<Local>,
+    tax: i64,
+    pub source: String,
+    pub target: String
+}
+
+impl Transfer {
+    pub fn new(cmd: Cmd) -> Result<Transfer, LindaError> {
+        match cmd.op {
+            Some(Op::Transfer { amount, source, target }) =>
+                Ok(Transfer { created_at: cmd.created_at, tax: amount, source, target }),
+            _ => Err(LindaError::Unsupported(cmd.shape())),
+        }
+    }
+    const INSERT: &'static str = "INSERT INTO transfer (created_at, tax, source, target) VALUES (?1, ?2, ?3, ?4)";
+
+    fn values(&self) -> Vec<Value> {
+        vec![
+            Value::Integer(self.created_at.timestamp()),
+            Value::Integer(self.tax),
+            Value::Text(self.source.clone()),
+            Value::Text(self.target.clone()),
+        ]
+    }
+    /// Inserts the transfer and returns its id.
+    pub fn insert(&self, conn: &Connection) -> Result<i64> {
+        conn.execute(Transfer::INSERT, rusqlite::params_from_iter(self.values()))?;
+        Ok(conn.last_insert_rowid())
+    }
+    pub fn sql(&self) -> Vec<String> {
+        vec![show_sql(Transfer::INSERT, &self.values())]
+    }
+    /// Like `transfer #3: 500.00 checking -> savings (2024-05-03)`.
+    pub fn describe(&self, id: i64) -> String {
+        format!(
+            "transfer #{}: {} {} -> {} ({})", id, format_cents(self.tax),
+            self.source, self.target, self.created_at.format("%Y-%m-%d")
+        )
+    }
+    /// The command that would record this transfer again, on the same day.
+    pub fn to_cmd(&self) -> String {
+        [
+            format!("+{}", PartOfCmdKind::Digit(self.tax)),
+            PartOfCmdKind::Word(self.source.clone()).to_string(),
+            PartOfCmdKind::Word(self.target.clone()).to_string(),
+            self.created_at.format("@%Y-%m-%d").to_string(),
+        ].join(&SEP.to_string())
+    }
+    /// The transfers written by the command of `batch`, with their ids.
+    fn of_batch(conn: &Connection, batch: i64) -> Result<Vec<(i64, Transfer)>> {
+        let mut stmt = conn.prepare("SELECT id, created_at, tax, source, target FROM transfer WHERE batch = ?1 ORDER BY id")?;
+        let transfers = stmt.query_map([batch], |row| {
+            let secs: i64 = row.get(1)?;
+            Ok((row.get(0)?, Transfer {
+                created_at: from_timestamp(secs).ok_or(rusqlite::Error::IntegralValueOutOfRange(1, secs))?,
+                tax: row.get(2)?,
+                source: row.get(3)?,
+                target: row.get(4)?,
+            }))
+        })?;
+        transfers.collect()
+    }
+}
+
+/// Fills the `?N` placeholders of a statement with SQL literals, for showing
+/// it rather than running it.
+fn show_sql(sql: &str, values: &[Value]) -> String {
+    let sql = sql.split_whitespace().collect::<Vec<_>>().join(" ");
+    values.iter().enumerate().rev().fold(sql, |sql, (i, value)| {
+        let literal = match value {
+            Value::Null => String::from("NULL"),
+            Value::Integer(n) => n.to_string(),
+            Value::Real(r) => r.to_string(),
+            Value::Text(t) => format!("'{}'", t.replace('\'', "''")),
+            Value::Blob(b) => format!("<{} bytes>", b.len()),
+        };
+        sql.replace(&format!("?{}", i + 1), &literal)
+    })
+}
+
+/// A row a command writes.
+#[derive(Debug)]
+pub enum Entry {
+    Order(Tr),
+    Transfer(Transfer),
+}
+
+impl Entry {
+    /// Everything `cmd` writes: the order or transfer itself, and the tax
+    /// share of an order with a rate. Orders without a currency are in `base`.
+    pub fn of(cmd: Cmd, base: &str) -> Result<Vec<Entry>, LindaError> {
+        match cmd.kind_of()? {
+            Some(CmdKind::Transfer) => Ok(vec![Entry::Transfer(Transfer::new(cmd)?)]),
+            Some(CmdKind::Order(_)) => {
+                let rate = match &cmd.op {
+                    Some(Op::Order(order)) => order.rate,
+                    _ => None,
+                };
+                let transaction = Tr::new(cmd, base)?;
+                let share = rate.map(|rate| Tr {
+                    kind: OrderKind::Expense,
+                    tax: share_of(transaction.tax, rate),
+                    category: String::from("tax"),
+                    description: Some(format!("{}% of {}", compact_cents(rate), transaction.category)),
+                    duration: 0,
+                    tags: transaction.tags.clone(),
+                    currency: transaction.currency.clone(),
+                    account: transaction.account.clone(),
+                    ..transaction
+                });
+                Ok(std::iter::once(transaction).chain(share).map(Entry::Order).collect())
+            },
+            None => Err(LindaError::Unsupported(cmd.shape())),
+        }
+    }
+    pub fn sql(&self) -> Vec<String> {
+        match self {
+            Entry::Order(transaction) => transaction.sql(),
+            Entry::Transfer(transfer) => transfer.sql(),
+        }
+    }
+    pub fn accounts(&self) -> Vec<&str> {
+        match self {
+            Entry::Order(transaction) => vec![&transaction.account],
+            Entry::Transfer(transfer) => vec![&transfer.source, &transfer.target],
+        }
+    }
+}
+
+impl Cmd {
+    pub fn shape(&self) -> String {
+        self.pack.iter().map(|p| p.describe()).collect::<Vec<_>>().join(", ")
+    }
+    /// How a command with this modifier is written, for errors.
+    fn expected_shape(&self) -> &'static str {
+        match self.pack[0] {
+            PartOfCmdKind::Mod('+') => TRANSFER_SHAPE,
+            _ => ORDER_SHAPE,
+        }
+    }
+    pub fn validate(&self) -> Result<(), InvalidCmdError> {
+        let fields: &[Field] = match self.pack[0] {
+            PartOfCmdKind::Mod('+') => &[Field::Amount, Field::Source, Field::Target],
+            _ => &[Field::Amount, Field::Category],
+        };
+        for (i, field) in fields.iter().enumerate() {
+            self.expect(i + 1, *field)?;
+        }
+        match &self.pack[..] {
+            [_, PartOfCmdKind::Digit(d), ..] if *d <= 0 => return Err(InvalidCmdError::InvalidAmount(*d)),
+            [PartOfCmdKind::Mod('+'), _, PartOfCmdKind::Word(source), PartOfCmdKind::Word(target)] if source == target =>
+                return Err(InvalidCmdError::SameAccount(source.clone())),
+            _ => {},
+        }
+        self.expect_end(fields.len() + 1)?;
+        match self.pack[0] {
+            PartOfCmdKind::Mod(ch) if ch != '&' && self.pack.iter().any(|p| matches!(p, PartOfCmdKind::Rate(_))) =>
+                Err(InvalidCmdError::RateNotIncome(ch)),
+            _ => Ok(()),
+        }
+    }
+    /// Rejects fields past the known shape. Only orders have a tail, of at
+    /// most one description, one duration and one rate.
+    fn expect_end(&self, from: usize) -> Result<(), InvalidCmdError> {
+        let is_order = !matches!(self.pack[0], PartOfCmdKind::Mod('+'));
+        let mut seen = Vec::new();
+        for (i, part) in self.pack.iter().enumerate().skip(from) {
+            let kind = std::mem::discriminant(part);
+            if !is_order || !Cmd::is_tail_part(part) || seen.contains(&kind) {
+                return Err(InvalidCmdError::Unexpected(i, part.to_string(), self.expected_shape()));
+            }
+            seen.push(kind);
+        }
+        Ok(())
+    }
+    fn expect(&self, pos: usize, field: Field) -> Result<(), InvalidCmdError> {
+        match self.pack.get(pos) {
+            None => Err(InvalidCmdError::Missing(field, self.expected_shape())),
+            // Only a description may be left empty on purpose with `""`.
+            Some(PartOfCmdKind::Word(w)) if w.trim().is_empty() =>
+                Err(InvalidCmdError::Missing(field, self.expected_shape())),
+            Some(part) if field.accepts(part) => Ok(()),
+            Some(part) => Err(InvalidCmdError::Mismatch(field, part.to_string())),
+        }
+    }
+    pub fn kind_of(&self) -> Result<Option<CmdKind>, LindaError> {
+        Ok(Cmd::read_op(&self.pack, &self.currency)?.map(|op| match op {
+            Op::Order(order) => CmdKind::Order(order.kind),
+            Op::Transfer { .. } => CmdKind::Transfer,
+        }))
+    }
+    /// Reads a pack into typed fields if it has the shape of an order or a
+    /// transfer.
+    fn read_op(pack: &[PartOfCmdKind], currency: &Option<String>) -> Result<Option<Op>, LindaError> {
+        Ok(match pack {
+            [
+                PartOfCmdKind::Mod('+'),
+                PartOfCmdKind::Digit(amount),
+                PartOfCmdKind::Word(source),
+                PartOfCmdKind::Word(target),
+            ] => Some(Op::Transfer { amount: *amount, source: source.clone(), target: target.clone() }),
+            // A `+` with anything else is a malformed transfer, not an order.
+            [PartOfCmdKind::Mod('+'), ..] => None,
+            [
+                PartOfCmdKind::Mod(ch),
+                PartOfCmdKind::Digit(amount),
+                PartOfCmdKind::Word(category),
+                rest @ ..
+            ] if Cmd::is_order_tail(rest) => Some(Op::Order(Order {
+                kind: OrderKind::new(*ch)?,
+                amount: *amount,
+                category: category.clone(),
+                description: rest.iter().find_map(|p| String::try_from(p).ok()),
+                duration: rest.iter().find_map(|p| i32::try_from(p).ok()).unwrap_or(0),
+                rate: rest.iter().find_map(|p| match p {
+                    PartOfCmdKind::Rate(r) => Some(*r),
+                    _ => None,
+                }),
+                currency: currency.clone(),
+            })),
+            _ => None,
+        })
+    }
+    /// An order may be followed by at most one description, one duration and
+    /// one rate, in any order.
+    fn is_order_tail(rest: &[PartOfCmdKind]) -> bool {
+        let kinds: Vec<_> = rest.iter().map(std::mem::discriminant).collect();
+        rest.iter().all(Cmd::is_tail_part)
+            && kinds.iter().enumerate().all(|(i, kind)| !kinds[..i].contains(kind))
+    }
+    fn is_tail_part(part: &PartOfCmdKind) -> bool {
+        matches!(part, PartOfCmdKind::Word(_) | PartOfCmdKind::Duration(_) | PartOfCmdKind::Rate(_))
+    }
+}
+
+impl FromStr for Cmd {
+    type Err = LindaError;
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        Cmd::parse_with(text, &Syntax::default(), Local::now())
+    }
+}
+
+impl Cmd {
+    /// Parses a command as if it was entered at `now`, which relative dates
+    /// are resolved against.
+    ///
+    /// Orders are positional: `<mod><amount>[,<minutes>],<category>` followed
+    /// by an optional free text description. The duration is either a bare
+    /// whole number right after the amount (`&200,90,consulting`) or a
+    /// `~minutes` field anywhere after the category (`&200,consulting,~90`).
+    ///
+    /// A command starting with a letter is in the verbose form, where the
+    /// modifier is spelled out and fields are separated by whitespace:
+    /// `expense 250 groceries weekly shop` reads as `>250,groceries,weekly shop`.
+    /// A first word that names a shorthand is replaced by its template, and
+    /// the words after it replace the fields of the template in order, so
+    /// with `coffee` standing for `>1.80,coffee`, `coffee 2.20` is `>2.20,coffee`.
+    pub fn parse_with(text: &str, syntax: &Syntax, now: DateTime<Local>) -> Result<Cmd, LindaError> {
+        let line = text.trim();
+        let mut text = line;
+        let mut created_at = None;
+        if let Some(rest) = text.strip_prefix('@') {
+            let (date, cmd) = rest.split_once(char::is_whitespace).unwrap_or((rest, ""));
+            created_at = Some(parse_date(date, now).map_err(|e| e.at(1))?);
+            text = cmd.trim_start();
+        }
+        // Position of the modifier, after the date prefix if there is one.
+        let base = line[..line.len() - text.len()].chars().count();
+        let mut chars = text.chars();
+        let ch = chars.next().ok_or(ParseCmdError::new(ParseErrorKind::Empty, "").at(base))?;
+        let (ch, mut fields) = if ch.is_alphabetic() {
+            let (word, rest) = text.split_once(char::is_whitespace).unwrap_or((text, ""));
+            let offset = base + text.chars().count() - rest.chars().count();
+            if let Some(ch) = mod_of_word(word) {
+                (ch, verbose_fields(rest, ch, offset)?)
+            } else if let Some(template) = syntax.shorthands.get(&word.to_lowercase()) {
+                let bad = || ParseCmdError::new(ParseErrorKind::BadShorthand, word).at(base);
+                let ch = template.chars().next().filter(|c| MODS.contains(c)).ok_or_else(bad)?;
+                // The template was checked when it was defined, errors in it
+                // point at the name. Its tags, account and date are kept
+                // whatever is replaced.
+                let (tags, mut fields): (Vec<_>, Vec<_>) = split_fields(&template[ch.len_utf8()..], SEP, base)
+                    .map_err(|_| bad())?
+                    .into_iter()
+                    .map(|(f, quoted, _)| (f, quoted, base))
+                    .partition(|(f, quoted, _)| !quoted && (f.starts_with('@') || take_tags(f).0.is_empty() && !f.is_empty()));
+                for (i, field) in verbose_fields(rest, ch, offset)?.into_iter().enumerate() {
+                    match fields.get_mut(i) {
+                        Some(f) => *f = field,
+                        None => fields.push(field),
+                    }
+                }
+                fields.extend(tags);
+                (ch, fields)
+            } else {
+                return Err(ParseCmdError::new(ParseErrorKind::UnexpectedMod, word).at(base).into());
+            }
+        } else if MODS.contains(&ch) {
+            (ch, split_fields(chars.as_str(), syntax.sep, base + 1)?)
+        } else {
+            return Err(ParseCmdError::new(ParseErrorKind::UnexpectedMod, first_grapheme(text)).at(base).into());
+        };
+        if fields.iter().all(|(f, quoted, _)| f.is_empty() && !quoted) {
+            return Err(ParseCmdError::new(ParseErrorKind::NoFields, "").at(base + 1).into());
+        }
+        // A trailing separator is tolerated, other empty fields are mistakes.
+        // An empty value can still be given as "".
+        if fields.last().is_some_and(|(f, quoted, _)| f.is_empty() && !quoted) {
+            fields.pop();
+        }
+        if let Some(i) = fields.iter().position(|(f, quoted, _)| f.is_empty() && !quoted) {
+            return Err(ParseCmdError::new(ParseErrorKind::EmptyField(i + 1), "").at(fields[i].2).into());
+        }
+        // The date may also be a field anywhere after the amount, and so may
+        // the account, by a name that isn't a date.
+        let mut account = None;
+        while let Some(i) = fields.iter().skip(1).position(|(f, quoted, _)| !quoted && f.starts_with('@')) {
+            let (field, _, pos) = fields.remove(i + 1);
+            let (kind, slot) = match parse_date(&field[1..], now) {
+                Ok(date) => (ParseErrorKind::TwoDates, created_at.replace(date).map(drop)),
+                Err(_) if is_account_name(&field[1..]) && ch == '+' =>
+                    return Err(ParseCmdError::new(ParseErrorKind::TransferAccount, &field).at(pos).into()),
+                Err(_) if is_account_name(&field[1..]) =>
+                    (ParseErrorKind::TwoAccounts, account.replace(field[1..].to_string()).map(drop)),
+                Err(e) => return Err(e.at(pos + 1).into()),
+            };
+            if slot.is_some() {
+                return Err(ParseCmdError::new(kind, &field).at(pos).into());
+            }
+        }
+        let created_at = created_at.unwrap_or(now);
+        let mut pack: Vec<PartOfCmdKind> = vec![
+            PartOfCmdKind::Mod(ch)
+        ];
+        let mut tags = Vec::new();
+        let mut rate = None;
+        let mut currency = None;
+        let positional_duration = ch != '+' && fields.len() > 2 && !fields[1].1
+            && !fields[1].0.is_empty() && fields[1].0.chars().all(|c| c.is_ascii_digit());
+
+        for (i, (field, quoted, pos)) in fields.iter().enumerate() {
+            if positional_duration && i == 1 { continue; }
+            // Position of the field as if there was no positional duration.
+            let i = if positional_duration && i > 1 { i - 1 } else { i };
+            let mut field = field.clone();
+            if i >= 2 && !*quoted {
+                let found;
+                (field, found) = take_tags(&field);
+                let only_tags = field.is_empty() && !found.is_empty();
+                for tag in found {
+                    if !tags.contains(&tag) { tags.push(tag) }
+                }
+                if only_tags { continue; }
+            }
+            let ch = field.as_str();
+            let is_category = i == 1 && !matches!(pack[0], PartOfCmdKind::Mod('+'));
+            if *quoted {
+                pack.push(PartOfCmdKind::Word(if is_category { syntax.category(ch) } else { String::from(ch) }))
+            } else if ch.starts_with('%') {
+                pack.push(PartOfCmdKind::Rate(parse_rate(ch).map_err(|e| e.at(*pos))?))
+            } else if let Some(m) = ch.strip_prefix('~') {
+                let m = m.trim().parse().map_err(|_| ParseCmdError::new(ParseErrorKind::BadDuration, ch).at(*pos))?;
+                pack.push(PartOfCmdKind::Duration(m))
+            // Everything after the amount and the category is free text.
+            } else if i >= 2 {
+                pack.push(PartOfCmdKind::Word(String::from(ch)))
+            } else if i == 0 {
+                // A `%rate` may be written right after the amount.
+                let (ch, share) = match ch.find('%') {
+                    Some(at) => ch.split_at(at),
+                    None => (ch, ""),
+                };
+                if !share.is_empty() {
+                    rate = Some(parse_rate(share).map_err(|e| e.at(*pos + ch.chars().count()))?);
+                }
+                // Only orders may be in another currency.
+                let ch = if matches!(pack[0], PartOfCmdKind::Mod('+')) { ch } else {
+                    let (number, code) = split_currency(ch).map_err(|e| e.at(*pos))?;
+                    currency = code;
+                    number
+                };
+                let d = parse_cents(ch)
+                    .and_then(|d| d.ok_or_else(|| ParseCmdError::new(ParseErrorKind::BadAmount, ch)))
+                    .map_err(|e| e.at(*pos))?;
+                pack.push(PartOfCmdKind::Digit(d))
+            } else if let Some(d) = parse_cents(ch).ok().flatten() {
+                pack.push(PartOfCmdKind::Digit(d))
+            } else if is_category {
+                pack.push(PartOfCmdKind::Word(syntax.category(ch)))
+            } else {
+                pack.push(PartOfCmdKind::Word(String::from(ch)))
+            }
+        }
+        if positional_duration {
+            let (field, _, pos) = &fields[1];
+            let m = field.parse().map_err(|_| ParseCmdError::new(ParseErrorKind::BadDuration, field).at(*pos))?;
+            pack.push(PartOfCmdKind::Duration(m));
+        }
+        if let Some(r) = rate {
+            pack.push(PartOfCmdKind::Rate(r));
+        }
+        let op = Cmd::read_op(&pack, &currency)?;
+        Ok(Cmd { pack, created_at, tags, currency, account, op })
+    }
+}
+
+/// Whether `@name` names an account: a letter, then letters, digits, `-` and `_`.
+fn is_account_name(name: &str) -> bool {
+    name.starts_with(char::is_alphabetic) && name.chars().all(|c| c.is_alphanumeric() || c == '-' || c == '_')
+}
+
+/// Pulls `#tag` words out of a free text field, lowercased. Returns what is
+/// left of the text along with the tags.
+fn take_tags(text: &str) -> (String, Vec<String>) {
+    let (tags, words): (Vec<&str>, Vec<&str>) = text.split_whitespace()
+        .partition(|w| w.len() > 1 && w.starts_with('#'));
+    (words.join(" "), tags.into_iter().map(|t| t[1..].to_lowercase()).collect())
+}
+
+/// Splits a line into commands on `CMD_SEP`, ignoring separators inside
+/// quoted fields. A trailing separator is tolerated.
+pub fn split_cmds(text: &str) -> Vec<&str> {
+    let mut cmds = Vec::new();
+    let mut start = 0;
+    let mut quoted = false;
+    let mut chars = text.char_indices().peekable();
+    while let Some((i, ch)) = chars.next() {
+        match ch {
+            '\\' if quoted && matches!(chars.peek(), Some((_, '"'))) => { chars.next(); },
+            '"' => quoted = !quoted,
+            c if c == CMD_SEP && !quoted => {
+                cmds.push(&text[start..i]);
+                start = i + c.len_utf8();
+            },
+            _ => {},
+        }
+    }
+    let last = &text[start..];
+    if cmds.is_empty() || !last.trim().is_empty() {
+        cmds.push(last);
+    }
+    cmds
+}
+
+/// Parses and validates every command of a line, naming the first one that
+/// fails.
+pub fn parse_batch(text: &str, syntax: &Syntax) -> Result<Vec<Cmd>, BatchError> {
+    let now = Local::now();
+    // Editors on Windows like to start files with a byte order mark.
+    let text = text.strip_prefix('\u{feff}').unwrap_or(text);
+    split_cmds(text).into_iter().enumerate().map(|(i, text)| {
+        let fail = |reason: String| BatchError { index: i + 1, line: None, text: String::from(text.trim()), reason };
+        let cmd = Cmd::parse_with(text, syntax, now).map_err(|e| fail(e.to_string()))?;
+        cmd.validate().map_err(|e| fail(e.to_string()))?;
+        match cmd.kind_of().map_err(|e| fail(e.to_string()))? {
+            Some(_) => Ok(cmd),
+            None => Err(fail(LindaError::Unsupported(cmd.shape()).to_string())),
+        }
+    }).collect()
+}
+
+/// Parses the commands of every line of `text`, as from a file or a pipe,
+/// skipping blank lines. Errors name the line.
+pub fn parse_lines(text: &str, syntax: &Syntax) -> Result<Vec<Cmd>, BatchError> {
+    let text = text.strip_prefix('\u{feff}').unwrap_or(text);
+    let mut cmds = Vec::new();
+    for (i, line) in text.lines().enumerate() {
+        if line.trim().is_empty() { continue; }
+        cmds.extend(parse_batch(line, syntax).map_err(|e| BatchError { line: Some(i + 1), ..e })?);
+    }
+    Ok(cmds)
+}
+
+/// Splits fields on `sep`, trimming unquoted ones. A field wrapped in double
+/// quotes is taken verbatim and may contain `sep`; a quote inside it is
+/// written as `""` or `\"`. Each field comes with a flag telling whether it was
+/// quoted and with its position, counting from `offset`, the position of
+/// `text` within the whole command.
+fn split_fields(text: &str, sep: char, offset: usize) -> Result<Vec<(String, bool, usize)>, ParseCmdError> {
+    let mut fields = Vec::new();
+    let mut field = String::new();
+    let mut quoted = false;
+    let mut start = None;
+    let mut chars = text.chars().enumerate().peekable();
+    while let Some((i, ch)) = chars.next() {
+        match ch {
+            '"' if !quoted && field.trim().is_empty() => {
+                field.clear();
+                quoted = true;
+                start = Some(i);
+                loop {
+                    match chars.next() {
+                        None => return Err(ParseCmdError::new(ParseErrorKind::UnterminatedQuote, "\"").at(offset + i)),
+                        Some((_, '\\' | '"')) if matches!(chars.peek(), Some((_, '"'))) => {
+                            chars.next();
+                            field.push('"');
+                        },
+                        Some((_, '"')) => break,
+                        Some((_, c)) => field.push(c),
+                    }
+                }
+            },
+            c if c == sep => {
+                fields.push(finish_field(field, quoted, offset + start.unwrap_or(i)));
+                field = String::new();
+                quoted = false;
+                start = None;
+            },
+            c if quoted && !c.is_whitespace() => {
+                return Err(ParseCmdError::new(ParseErrorKind::AfterQuote, &c.to_string()).at(offset + i));
+            },
+            _ if quoted => {},
+            c => {
+                if start.is_none() && !c.is_whitespace() { start = Some(i) }
+                field.push(c)
+            },
+        }
+    }
+    fields.push(finish_field(field, quoted, offset + start.unwrap_or(text.chars().count())));
+    Ok(fields)
+}
+
+/// Splits the fields of a verbose command on whitespace. Past the amount and
+/// the category of an order, plain words run together into the description,
+/// while `~minutes`, `%rate` and quoted words stay fields of their own.
+fn verbose_fields(text: &str, ch: char, offset: usize) -> Result<Vec<(String, bool, usize)>, ParseCmdError> {
+    let is_tail = |w: &str| w.starts_with(['~', '%', '@']);
+    let mut fields: Vec<(String, bool, usize)> = Vec::new();
+    for (word, quoted, pos) in split_fields(text, ' ', offset)? {
+        if word.is_empty() && !quoted { continue; }
+        let positional_duration = fields.get(1)
+            .is_some_and(|(f, q, _)| !q && f.chars().all(|c| c.is_ascii_digit()));
+        let description = if positional_duration { 3 } else { 2 };
+        let joins = ch != '+' && fields.len() > description && !quoted && !is_tail(&word);
+        match fields.last_mut() {
+            Some((last, false, _)) if joins && !is_tail(last) => {
+                last.push(' ');
+                last.push_str(&word);
+            },
+            _ => fields.push((word, quoted, pos)),
+        }
+    }
+    Ok(fields)
+}
+
+fn finish_field(field: String, quoted: bool, pos: usize) -> (String, bool, usize) {
+    if quoted { (field, true, pos) } else { (String::from(field.trim()), false, pos) }
+}
+
+/// Resolves a date prefix to midday of that day in local time.
+pub fn parse_date(text: &str, now: DateTime<Local>) -> Result<DateTime<Local>, ParseCmdError> {
+    let bad = || ParseCmdError::new(ParseErrorKind::BadDate, text);
+    let date = parse_day(text, now)?;
+    Local.from_local_datetime(&date.and_hms_opt(12, 0, 0).ok_or_else(bad)?)
+        .earliest()
+        .ok_or_else(bad)
+}
+
+/// Besides `YYYY-MM-DD` accepts `today`, `yesterday`, `-Nd`, `-Nw` and weekday
+/// names (`mon`, `friday`), which mean the most recent such day.
+pub fn parse_day(text: &str, now: DateTime<Local>) -> Result<NaiveDate, ParseCmdError> {
+    let bad = || ParseCmdError::new(ParseErrorKind::BadDate, text);
+    let today = now.date_naive();
+    let days_back = |n: i64| today.checked_sub_signed(chrono::Duration::days(n)).ok_or_else(bad);
+    let date = match text.to_lowercase().as_str() {
+        "today" => today,
+        "yesterday" => days_back(1)?,
+        rel if rel.starts_with('-') && (rel.ends_with('d') || rel.ends_with('w')) => {
+            let n: i64 = rel[1..rel.len() - 1].parse().map_err(|_| bad())?;
+            days_back(if rel.ends_with('w') { n * 7 } else { n })?
+        },
+        day => match day.parse::<Weekday>() {
+            Ok(wd) => days_back(
+                (today.weekday().num_days_from_monday() as i64 - wd.num_days_from_monday() as i64).rem_euclid(7)
+            )?,
+            Err(_) => NaiveDate::parse_from_str(text, "%Y-%m-%d").map_err(|_| bad())?,
+        },
+    };
+    Ok(date)
+}
+
+/// A range of whole local days, as Unix seconds with an exclusive end.
+#[derive(Debug, Clone, Copy)]
+pub struct Period {
+    pub since: i64,
+    pub until: i64,
+}
+
+impl Period {
+    pub fn new(since: Option<NaiveDate>, until: Option<NaiveDate>) -> Period {
+        Period {
+            since: since.map_or(i64::MIN, start_of_day),
+            until: until.and_then(|d| d.succ_opt()).map_or(i64::MAX, start_of_day),
+        }
+    }
+}
+
+/// Where clocks skip midnight the day starts at the first minute that exists.
+fn start_of_day(date: NaiveDate) -> i64 {
+    let midnight = date.and_time(NaiveTime::MIN);
+    (0..24 * 60)
+        .find_map(|m| Local.from_local_datetime(&(midnight + chrono::Duration::minutes(m))).earliest())
+        .map_or(0, |d| d.timestamp())
+}
+
+/// Parses an amount into cents. Returns `None` when the text doesn't start
+/// like a number, so it can be treated as a word; anything else that starts
+/// with a digit but isn't a valid amount is an error. Amounts may be simple
+/// expressions, see `eval_cents`.
+pub fn parse_cents(text: &str) -> Result<Option<i64>, ParseCmdError> {
+    let digits = text.strip_prefix('-').unwrap_or(text);
+    if !digits.starts_with(|c: char| c.is_ascii_digit() || c == '.') {
+        return Ok(None);
+    }
+    if digits.contains(OPS) {
+        return eval_cents(text).map(Some);
+    }
+    parse_number(text).map(Some)
+}
+
+const OPS: [char; 4] = ['+', '-', '*', '/'];
+
+/// Splits a trailing currency code off an amount, `45EUR` is 45 in `EUR`. A
+/// single letter is left alone, it's a `k` or `m` multiplier.
+fn split_currency(text: &str) -> Result<(&str, Option<String>), ParseCmdError> {
+    let number = text.trim_end_matches(|c: char| c.is_ascii_alphabetic());
+    let code = &text[number.len()..];
+    // A word without a number is a mistyped amount, not a currency.
+    if code.len() <= 1 || number.is_empty() {
+        return Ok((text, None));
+    }
+    let currency = parse_currency(code).map_err(|e| e.at(number.chars().count()))?;
+    Ok((number, Some(currency)))
+}
+
+/// Parses a three letter currency code, uppercased.
+pub fn parse_currency(text: &str) -> Result<String, ParseCmdError> {
+    if text.len() == 3 && text.chars().all(|c| c.is_ascii_alphabetic()) {
+        Ok(text.to_ascii_uppercase())
+    } else {
+        Err(ParseCmdError::new(ParseErrorKind::BadCurrency, text))
+    }
+}
+
+/// Parses `%13` or `%7.5` into hundredths of a percent, from 0 to 100.
+fn parse_rate(text: &str) -> Result<i64, ParseCmdError> {
+    match parse_number(&text[1..]) {
+        Ok(rate) if (0..=10_000).contains(&rate) => Ok(rate),
+        _ => Err(ParseCmdError::new(ParseErrorKind::BadRate, text)),
+    }
+}
+
+/// The `rate` share of `cents`, rounded to the nearest cent with halves away
+/// from zero, so 13% of 0.50 is 0.07.
+pub fn share_of(cents: i64, rate: i64) -> i64 {
+    div_round(i128::from(cents) * i128::from(rate), 10_000) as i64
+}
+
+/// Parses `12`, `12.5`, `12.` or `.5` into cents, optionally scaled by a `k`
+/// or `m` suffix (`1.2k` is 1200).
+pub fn parse_number(text: &str) -> Result<i64, ParseCmdError> {
+    let (sign, digits) = match text.strip_prefix('-') {
+        Some(rest) => (-1, rest),
+        None => (1, text),
+    };
+    let bad = || ParseCmdError::new(ParseErrorKind::BadAmount, text);
+    let out_of_range = || ParseCmdError::new(ParseErrorKind::AmountOutOfRange, text);
+    let (number, multiplier) = match digits.chars().last().map(|c| c.to_ascii_lowercase()) {
+        Some('k') => (&digits[..digits.len() - 1], 1_000),
+        Some('m') => (&digits[..digits.len() - 1], 1_000_000),
+        _ => (digits, 1),
+    };
+    if !number.chars().any(|c| c.is_ascii_digit()) || !number.chars().all(|c| c.is_ascii_digit() || c == '.') {
+        return Err(bad());
+    }
+    let (whole, frac) = number.split_once('.').unwrap_or((number, ""));
+    if frac.len() > 2 || frac.contains('.') { return Err(bad()); }
+    let whole: i64 = if whole.is_empty() { 0 } else { whole.parse().map_err(|_| out_of_range())? };
+    let frac: i64 = if frac.is_empty() { 0 } else { format!("{:0<2}", frac).parse().map_err(|_| bad())? };
+    whole.checked_mul(100)
+        .and_then(|w| w.checked_add(frac))
+        .and_then(|c| c.checked_mul(multiplier))
+        .map(|c| sign * c)
+        .ok_or_else(out_of_range)
+}
+
+/// Evaluates `+ - * /` over amounts with the usual precedence (`*` and `/`
+/// bind tighter, otherwise left to right), so `1200+350*2` is 1900. Every
+/// multiplication and division is rounded to the nearest cent, halves away
+/// from zero, so `1450/3` is 483.33 and `0.05/2` is 0.03.
+fn eval_cents(text: &str) -> Result<i64, ParseCmdError> {
+    let bad = || ParseCmdError::new(ParseErrorKind::BadExpression, text);
+    let out_of_range = || ParseCmdError::new(ParseErrorKind::AmountOutOfRange, text);
+    // A leading minus belongs to the first operand, not to an operator.
+    let (head, rest) = text.split_at(usize::from(text.starts_with('-')));
+    let mut operands = Vec::new();
+    let mut ops = Vec::new();
+    let mut start = 0;
+    for (i, ch) in rest.char_indices() {
+        if OPS.contains(&ch) {
+            operands.push(&rest[start..i]);
+            ops.push(ch);
+            start = i + 1;
+        }
+    }
+    operands.push(&rest[start..]);
+    let mut values = operands.iter().enumerate().map(|(i, operand)| {
+        let operand = if i == 0 { format!("{}{}", head, operand) } else { String::from(*operand) };
+        if operand.trim_start_matches('-').is_empty() { return Err(bad()); }
+        parse_number(&operand).map(i128::from).map_err(|e| match e.kind {
+            ParseErrorKind::AmountOutOfRange => out_of_range(),
+            _ => bad(),
+        })
+    }).collect::<Result<Vec<_>, _>>()?.into_iter();
+
+    let mut total: i128 = 0;
+    let mut sign = 1;
+    let mut term = values.next().ok_or_else(bad)?;
+    for (op, value) in ops.into_iter().zip(values) {
+        match op {
+            '*' => term = div_round(term.checked_mul(value).ok_or_else(out_of_range)?, 100),
+            '/' if value == 0 => return Err(bad()),
+            '/' => term = div_round(term.checked_mul(100).ok_or_else(out_of_range)?, value),
+            _ => {
+                total = total.checked_add(sign * term).ok_or_else(out_of_range)?;
+                sign = if op == '-' { -1 } else { 1 };
+                term = value;
+            },
+        }
+    }
+    total.checked_add(sign * term)
+        .and_then(|total| i64::try_from(total).ok())
+        .ok_or_else(out_of_range)
+}
+
+fn div_round(n: i128, d: i128) -> i128 {
+    let (q, r) = (n / d, n % d);
+    if 2 * r.abs() >= d.abs() { q + n.signum() * d.signum() } else { q }
+}
+
+/// Reads a stored `created_at` (Unix seconds) back into local time.
+pub fn from_timestamp(secs: i64) -> Option<DateTime<Local>> {
+    Local.timestamp_opt(secs, 0).single()
+}
+
+/// Like `format_cents`, but whole amounts have no fraction: `12` and `12.50`.
+fn compact_cents(cents: i64) -> String {
+    if cents % 100 == 0 { (cents / 100).to_string() } else { format_cents(cents) }
+}
+
+pub fn format_cents(cents: i64) -> String {
+    let sign = if cents < 0 { "-" } else { "" };
+    let cents = cents.unsigned_abs();
+    format!("{}{}.{:02}", sign, cents / 100, cents % 100)
+}
+
+/// Creates the database or brings an existing one up to date, and returns
+/// the schema version it was at, `None` if it was just created. Amounts
+/// stored before the currency column existed are taken to be in `currency`.
+/// With `force` an existing database is deleted first.
+pub fn init(db: &Path, currency: &str, force: bool) -> Result<Option<usize>, LindaError> {
+    let currency = parse_currency(currency)?;
+    if force && db.exists() {
+        // Whatever is there goes, even if it isn't a database linda can read.
+        remove_wal(db)?;
+        std::fs::remove_file(db)?;
+    }
+    if let Some(dir) = db.parent() {
+        std::fs::create_dir_all(dir)?;
+    }
+    let mut conn = connect(db)?;
+    match version_of(&conn)? {
+        None => {
+            create_schema(&mut conn)?;
+            Ok(None)
+        },
+        Some(version) => upgrade(&mut conn, db, version, &currency).map(Some),
+    }
+}
+
+/// How many transactions a database has, whatever its schema.
+pub fn count_transactions(db: &Path) -> Result<i64, LindaError> {
+    Ok(connect_read_only(db)?.query_row("SELECT COUNT(*) FROM `transaction`", [], |row| row.get(0))?)
+}
+
+type Migration = fn(&Connection, &str) -> Result<(), LindaError>;
+
+/// Schema changes in the order they were made, new ones go last. The first
+/// ones check what is there already, since databases made before
+/// `schema_version` existed start at version 0 whatever they contain.
+const MIGRATIONS: [Migration; 12] = [
+    create_tables, add_kind, create_budget, add_currency, create_indexes, create_shorthands, convert_text_timestamps,
+    add_account, create_categories, add_deleted_at, add_batches, create_accounts,
+];
+
+pub const SCHEMA_VERSION: usize = MIGRATIONS.len();
+
+/// The `--db` that SQLite keeps in memory, gone when the program exits.
+pub const MEMORY_DB: &str = ":memory:";
+
+/// The latest schema, which a new database gets at once rather than through
+/// every migration.
+const SCHEMA: &str = include_str!("../sql/scheme.sql");
+
+/// The first version whose migrations don't need to know the currency of
+/// old rows, the one that gave them a column for it.
+const CURRENCY_VERSION: usize = 4;
+
+/// Opens a database in memory with the latest schema.
+pub fn open_in_memory(currency: &str) -> Result<Connection, LindaError> {
+    parse_currency(currency)?;
+    let mut conn = Connection::open_in_memory()?;
+    configure(&conn)?;
+    create_schema(&mut conn)?;
+    Ok(conn)
+}
+
+fn create_schema(conn: &mut Connection) -> Result<(), LindaError> {
+    let tx = conn.transaction()?;
+    tx.execute_batch(SCHEMA)?;
+    tx.execute(
+        "INSERT INTO schema_version (version, applied_at) VALUES (?1, ?2)",
+        params![SCHEMA_VERSION, Local::now().timestamp()],
+    )?;
+    tx.commit()?;
+    Ok(())
+}
+
+fn schema_version(conn: &Connection) -> Result<usize, LindaError> {
+    Ok(conn.query_row("SELECT COALESCE(MAX(version), 0) FROM schema_version", [], |row| row.get(0))?)
+}
+
+/// Opens a database whatever its schema, to be read from a command in one
+/// terminal while another writes to it. See `configure`.
+fn connect(db: &Path) -> Result<Connection, LindaError> {
+    let conn = Connection::open(db)?;
+    configure(&conn)?;
+    Ok(conn)
+}
+
+/// Like `connect`, for a database that is only read and so is left as it
+/// is, down to its journal mode.
+fn connect_read_only(db: &Path) -> Result<Connection, LindaError> {
+    let conn = Connection::open_with_flags(db, OpenFlags::SQLITE_OPEN_READ_ONLY)?;
+    conn.busy_timeout(BUSY_TIMEOUT)?;
+    Ok(conn)
+}
+
+/// How long a connection waits for another one to finish writing.
+const BUSY_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(5);
+
+/// Makes a connection wait `BUSY_TIMEOUT` for another one to finish writing
+/// rather than fail at once, keeps readers and the writer from blocking each
+/// other with the write-ahead log, and has SQLite enforce foreign keys.
+fn configure(conn: &Connection) -> Result<(), LindaError> {
+    conn.busy_timeout(BUSY_TIMEOUT)?;
+    // Answers with the mode it ended up in, `memory` for an in-memory one.
+    conn.query_row("PRAGMA journal_mode = WAL", [], |row| row.get::<_, String>(0))?;
+    conn.pragma_update(None, "foreign_keys", true)?;
+    Ok(())
+}
+
+/// Deletes the write-ahead log next to a database, so that none of it is
+/// read into whatever takes the database's place.
+fn remove_wal(db: &Path) -> std::io::Result<()> {
+    for suffix in ["-wal", "-shm"] {
+        let mut sidecar = db.as_os_str().to_os_string();
+        sidecar.push(suffix);
+        match std::fs::remove_file(&sidecar) {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => return Err(e),
+            _ => (),
+        }
+    }
+    Ok(())
+}
+
+/// The schema version of a database, 0 for one from before versions were
+/// kept and `None` for one without any tables.
+fn version_of(conn: &Connection) -> Result<Option<usize>, LindaError> {
+    let tables: Vec<String> = conn.prepare("SELECT name FROM sqlite_master WHERE type = 'table'")?
+        .query_map([], |row| row.get(0))?
+        .collect::<Result<_>>()?;
+    match tables.iter().any(|t| t == "schema_version") {
+        true => Ok(Some(schema_version(conn)?)),
+        false if tables.is_empty() => Ok(None),
+        false => Ok(Some(0)),
+    }
+}
+
+/// Opens a database and brings it up to the schema this version knows. One
+/// from before amounts had a currency is left for `init`, as only it is told
+/// which currency they are in, and one that is ahead isn't touched at all. A
+/// missing file, or one without any tables, hasn't been initialized.
+fn open(db: &Path) -> Result<Connection, LindaError> {
+    // Opening would create an empty file with none of the tables.
+    if !db.exists() {
+        return Err(LindaError::NotInitialized(db.to_path_buf()));
+    }
+    let mut conn = connect(db)?;
+    match version_of(&conn)? {
+        None => Err(LindaError::NotInitialized(db.to_path_buf())),
+        Some(v) if v < CURRENCY_VERSION => Err(LindaError::OutdatedSchema(v)),
+        Some(v) => {
+            // Past `CURRENCY_VERSION` no migration reads the currency.
+            upgrade(&mut conn, db, v, BASE_CURRENCY)?;
+            Ok(conn)
+        },
+    }
+}
+
+/// Where `upgrade` copies a database at `version` before migrating it, like
+/// `linda.db.v5.bak`.
+pub fn backup_path(db: &Path, version: usize) -> PathBuf {
+    let mut name = db.as_os_str().to_os_string();
+    name.push(format!(".v{}.bak", version));
+    PathBuf::from(name)
+}
+
+/// Migrates a database that is at `version`, after copying it to
+/// `backup_path` so that a migration gone wrong loses nothing. A copy left
+/// by an earlier try is kept, as nothing can have been written since.
+fn upgrade(conn: &mut Connection, db: &Path, version: usize, currency: &str) -> Result<usize, LindaError> {
+    if version < SCHEMA_VERSION {
+        let backup = backup_path(db, version);
+        if !backup.exists() {
+            conn.execute("VACUUM INTO ?1", [backup.to_string_lossy()])?;
+        }
+    }
+    migrate(conn, currency)
+}
+
+/// Applies the migrations a database is missing, each in its own SQL
+/// transaction, and returns the version it was at.
+fn migrate(conn: &mut Connection, currency: &str) -> Result<usize, LindaError> {
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS schema_version (
+          version INTEGER PRIMARY KEY,
+          applied_at INTEGER NOT NULL
+        )",
+        (),
+    )?;
+    let from = schema_version(conn)?;
+    if from > SCHEMA_VERSION {
+        return Err(LindaError::NewerSchema(from));
+    }
+    for (version, migration) in MIGRATIONS.iter().enumerate().skip(from) {
+        let tx = conn.transaction()?;
+        migration(&tx, currency)?;
+        tx.execute(
+            "INSERT INTO schema_version (version, applied_at) VALUES (?1, ?2)",
+            params![version + 1, Local::now().timestamp()],
+        )?;
+        tx.commit()?;
+    }
+    Ok(from)
+}
+
+fn create_tables(conn: &Connection, _: &str) -> Result<(), LindaError> {
+    conn.execute_batch(
+        "CREATE TABLE IF NOT EXISTS `transaction` (
+          id INTEGER PRIMARY KEY AUTOINCREMENT,
+          created_at INTEGER NOT NULL,
+          kind TEXT NOT NULL DEFAULT 'income',
+          tax INTEGER NOT NULL,
+          category TEXT NOT NULL,
+          duration INTEGER DEFAULT 0,
+          description TEXT
+        );
+        CREATE TABLE IF NOT EXISTS `transfer` (
+          id INTEGER PRIMARY KEY AUTOINCREMENT,
+          created_at INTEGER NOT NULL,
+          tax INTEGER NOT NULL,
+          source TEXT NOT NULL,
+          target TEXT NOT NULL
+        );
+        CREATE TABLE IF NOT EXISTS tag (
+          id INTEGER PRIMARY KEY AUTOINCREMENT,
+          name TEXT NOT NULL UNIQUE
+        );
+        CREATE TABLE IF NOT EXISTS transaction_tag (
+          transaction_id INTEGER NOT NULL REFERENCES `transaction`(id) ON DELETE CASCADE,
+          tag_id INTEGER NOT NULL REFERENCES tag(id),
+          PRIMARY KEY (transaction_id, tag_id)
+        );"
+    )?;
+    Ok(())
+}
+
+/// `create_tables` leaves out the kind of tables made before it, which were
+/// all income.
+fn add_kind(conn: &Connection, _: &str) -> Result<(), LindaError> {
+    let has_kind: bool = conn.query_row(
+        "SELECT COUNT(*) > 0 FROM pragma_table_info('transaction') WHERE name = 'kind'",
+        [],
+        |row| row.get(0),
+    )?;
+    if !has_kind {
+        conn.execute("ALTER TABLE `transaction` ADD COLUMN kind TEXT NOT NULL DEFAULT 'income'", ())?;
+    }
+    Ok(())
+}
+
+fn create_budget(conn: &Connection, _: &str) -> Result<(), LindaError> {
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS budget (
+          category TEXT PRIMARY KEY,
+          monthly_limit INTEGER NOT NULL
+        )",
+        (),
+    )?;
+    Ok(())
+}
+
+fn add_currency(conn: &Connection, currency: &str) -> Result<(), LindaError> {
+    let has_currency: bool = conn.query_row(
+        "SELECT COUNT(*) > 0 FROM pragma_table_info('transaction') WHERE name = 'currency'",
+        [],
+        |row| row.get(0),
+    )?;
+    if !has_currency {
+        conn.execute(
+            &format!("ALTER TABLE `transaction` ADD COLUMN currency TEXT NOT NULL DEFAULT '{}'", currency),
+            (),
+        )?;
+    }
+    Ok(())
+}
+
+/// Reports scan by date and budgets by category.
+fn create_indexes(conn: &Connection, _: &str) -> Result<(), LindaError> {
+    conn.execute_batch(
+        "CREATE INDEX IF NOT EXISTS transaction_created_at ON `transaction`(created_at);
+        CREATE INDEX IF NOT EXISTS transaction_category ON `transaction`(category);"
+    )?;
+    Ok(())
+}
+
+fn create_shorthands(conn: &Connection, _: &str) -> Result<(), LindaError> {
+    conn.execute(
+        "CREATE TABLE shorthand (
+          name TEXT PRIMARY KEY,
+          template TEXT NOT NULL
+        )",
+        (),
+    )?;
+    Ok(())
+}
+
+/// Turns `created_at` values written by hand as dates, like `2024-03-01 12:00`,
+/// into Unix seconds. A date without an offset is taken as UTC. Text SQLite
+/// can't read as a date is left for reads to complain about.
+fn convert_text_timestamps(conn: &Connection, _: &str) -> Result<(), LindaError> {
+    for table in ["`transaction`", "transfer"] {
+        conn.execute(
+            &format!(
+                "UPDATE {} SET created_at = CAST(strftime('%s', created_at) AS INTEGER)
+                 WHERE typeof(created_at) = 'text' AND strftime('%s', created_at) IS NOT NULL", table
+            ),
+            (),
+        )?;
+    }
+    Ok(())
+}
+
+fn add_account(conn: &Connection, _: &str) -> Result<(), LindaError> {
+    conn.execute(
+        &format!("ALTER TABLE `transaction` ADD COLUMN account TEXT NOT NULL DEFAULT '{}'", DEFAULT_ACCOUNT),
+        (),
+    )?;
+    Ok(())
+}
+
+/// Moves the category names into their own table, which transactions refer
+/// to by id like tags. Budgets stay by name, they may be set before the
+/// category is first used.
+fn create_categories(conn: &Connection, _: &str) -> Result<(), LindaError> {
+    conn.execute_batch(
+        "CREATE TABLE category (
+          id INTEGER PRIMARY KEY AUTOINCREMENT,
+          name TEXT NOT NULL UNIQUE
+        );
+        INSERT INTO category (name) SELECT DISTINCT category FROM `transaction` ORDER BY category;
+        ALTER TABLE `transaction` ADD COLUMN category_id INTEGER REFERENCES category(id);
+        UPDATE `transaction` SET category_id = (SELECT id FROM category WHERE name = `transaction`.category);
+        DROP INDEX IF EXISTS transaction_category;
+        ALTER TABLE `transaction` DROP COLUMN category;
+        CREATE INDEX transaction_category ON `transaction`(category_id);"
+    )?;
+    Ok(())
+}
+
+/// When a transaction was deleted, it is kept until purged.
+fn add_deleted_at(conn: &Connection, _: &str) -> Result<(), LindaError> {
+    conn.execute("ALTER TABLE `transaction` ADD COLUMN deleted_at INTEGER", ())?;
+    Ok(())
+}
+
+/// Groups the rows written by one command, for `undo`. Older rows are a
+/// batch each, the transfers after the orders.
+fn add_batches(conn: &Connection, _: &str) -> Result<(), LindaError> {
+    conn.execute_batch(
+        "ALTER TABLE `transaction` ADD COLUMN batch INTEGER;
+        ALTER TABLE transfer ADD COLUMN batch INTEGER;
+        UPDATE `transaction` SET batch = id;
+        UPDATE transfer SET batch = id + (SELECT COALESCE(MAX(id), 0) FROM `transaction`);"
+    )?;
+    Ok(())
+}
+
+/// Moves the account names into their own table, like categories, with the
+/// default account and the ones transfers have used.
+fn create_accounts(conn: &Connection, _: &str) -> Result<(), LindaError> {
+    conn.execute_batch(
+        "CREATE TABLE account (
+          id INTEGER PRIMARY KEY AUTOINCREMENT,
+          name TEXT NOT NULL UNIQUE
+        );"
+    )?;
+    conn.execute("INSERT INTO account (name) VALUES (?1)", [DEFAULT_ACCOUNT])?;
+    conn.execute_batch(
+        "INSERT OR IGNORE INTO account (name)
+          SELECT account FROM `transaction` UNION SELECT source FROM transfer UNION SELECT target FROM transfer
+          ORDER BY 1;
+        ALTER TABLE `transaction` ADD COLUMN account_id INTEGER REFERENCES account(id);
+        UPDATE `transaction` SET account_id = (SELECT id FROM account WHERE name = `transaction`.account);
+        ALTER TABLE `transaction` DROP COLUMN account;"
+    )?;
+    Ok(())
+}
+
+/// What was stored: the rows and a line describing each, the warnings to
+/// show once the rows are committed, and debug dumps of the commands and rows.
+#[derive(Debug, Default)]
+pub struct Stored {
+    pub rows: Vec<Inserted>,
+    pub lines: Vec<String>,
+    pub warnings: Vec<String>,
+    pub debug: Vec<String>,
+}
+
+/// A row `store` wrote: an order in `transaction`, or a transfer.
+#[derive(Debug, PartialEq, Serialize)]
+pub struct Inserted {
+    pub id: i64,
+    /// `income`, `expense`, `refund` or `transfer`.
+    pub kind: String,
+}
+
+/// Inserts all commands inside a single SQL transaction, so either every
+/// one of them is stored or none is. Orders without a currency code are in
+/// `base`. The rows are reported only once they are committed.
+pub fn run(db: &Path, cmds: &[Cmd], base: &str, new_accounts: bool) -> Result<Stored, LindaError> {
+    store(&mut open(db)?, cmds, base, new_accounts)
+}
+
+/// Like `run`, but against a database in memory that is thrown away once its
+/// rows are returned, to try commands out without storing them.
+pub fn run_in_memory(cmds: &[Cmd], base: &str, new_accounts: bool) -> Result<(Stored, Vec<Record>), LindaError> {
+    let mut conn = open_in_memory(base)?;
+    let stored = store(&mut conn, cmds, base, new_accounts)?;
+    let records = Record::select(&conn, Period::new(None, None), None, u32::MAX, false)?;
+    Ok((stored, records))
+}
+
+/// An order about to be inserted that looks like one recorded already, as the
+/// command for it along with the recorded one's id and time.
+#[derive(Debug)]
+pub struct Duplicate {
+    pub text: String,
+    pub id: i64,
+    pub created_at: DateTime<Local>,
+}
+
+/// The orders of `cmds` that look entered already: there is one of the same
+/// kind, amount, currency, category and account on the same day, or within
+/// `hours` if given.
+pub fn duplicates(db: &Path, cmds: &[Cmd], base: &str, hours: Option<u32>) -> Result<Vec<Duplicate>, LindaError> {
+    find_duplicates(&open(db)?, cmds, base, hours)
+}
+
+fn find_duplicates(conn: &Connection, cmds: &[Cmd], base: &str, hours: Option<u32>) -> Result<Vec<Duplicate>, LindaError> {
+    let mut found = Vec::new();
+    for cmd in cmds {
+        for entry in Entry::of(cmd.clone(), base)? {
+            let Entry::Order(tr) = entry else { continue };
+            let within = match hours {
+                Some(hours) => {
+                    let secs = tr.created_at.timestamp();
+                    Period { since: secs - i64::from(hours) * 3600, until: secs + i64::from(hours) * 3600 + 1 }
+                },
+                None => Period::new(Some(tr.created_at.date_naive()), Some(tr.created_at.date_naive())),
+            };
+            let same: Option<(i64, i64)> = conn.query_row(
+                "SELECT id, created_at FROM `transaction`
+                 WHERE kind = ?1 AND tax = ?2 AND currency = ?3 AND category_id = (SELECT id FROM category WHERE name = ?4)
+                   AND account_id = (SELECT id FROM account WHERE name = ?5)
+                   AND created_at >= ?6 AND created_at < ?7 AND deleted_at IS NULL
+                 ORDER BY id DESC LIMIT 1",
+                params![tr.kind.to_string(), tr.tax, tr.currency, tr.category, tr.account, within.since, within.until],
+                |row| Ok((row.get(0)?, row.get(1)?)),
+            ).optional()?;
+            if let Some((id, secs)) = same {
+                let created_at = from_timestamp(secs).ok_or_else(|| LindaError::BadTimestamp(id, secs.to_string()))?;
+                found.push(Duplicate { text: tr.to_cmd(), id, created_at });
+            }
+        }
+    }
+    Ok(found)
+}
+
+/// Inserts `cmds` in one SQL transaction and describes each row, see `run`.
+/// Accounts that don't exist yet are an error unless `new_accounts`, which
+/// adds them.
+pub fn store(conn: &mut Connection, cmds: &[Cmd], base: &str, new_accounts: bool) -> Result<Stored, LindaError> {
+    let tx = conn.transaction_with_behavior(TransactionBehavior::Immediate)?;
+    let mut stored = Stored::default();
+    let first = next_batch(&tx)?;
+    for (batch, cmd) in (first..).zip(cmds) {
+        stored.debug.push(format!("{:?}", cmd));
+        let Stored { rows, lines, warnings, debug } = insert(&tx, cmd, base, new_accounts)?;
+        set_batch(&tx, batch)?;
+        stored.rows.extend(rows);
+        stored.lines.extend(lines);
+        stored.warnings.extend(warnings);
+        stored.debug.extend(debug);
+    }
+    tx.commit()?;
+    Ok(stored)
+}
+
+/// The batch the rows of the next command go in, see `undo`.
+fn next_batch(conn: &Connection) -> Result<i64> {
+    conn.query_row(
+        "SELECT COALESCE(MAX(batch), 0) + 1 FROM (SELECT batch FROM `transaction` UNION ALL SELECT batch FROM transfer)",
+        [],
+        |row| row.get(0),
+    )
+}
+
+/// Puts the rows written since the last call in `batch`, they are the only
+/// ones without one.
+fn set_batch(conn: &Connection, batch: i64) -> Result<()> {
+    conn.execute("UPDATE `transaction` SET batch = ?1 WHERE batch IS NULL", [batch])?;
+    conn.execute("UPDATE transfer SET batch = ?1 WHERE batch IS NULL", [batch])?;
+    Ok(())
+}
+
+/// Writes what `cmd` stands for, a line for each row.
+fn insert(conn: &Connection, cmd: &Cmd, base: &str, new_accounts: bool) -> Result<Stored, LindaError> {
+    let mut stored = Stored::default();
+    for entry in Entry::of(cmd.clone(), base)? {
+        for account in entry.accounts() {
+            check_account(conn, account, new_accounts)?;
+        }
+        match entry {
+            Entry::Transfer(transfer) => {
+                let id = transfer.insert(conn)?;
+                stored.debug.push(format!("{:?}", transfer));
+                stored.rows.push(Inserted { id, kind: String::from("transfer") });
+                stored.lines.push(format!("Recorded {}", transfer.describe(id)));
+            },
+            Entry::Order(transaction) => insert_tr(conn, &transaction, &mut stored)?,
+        }
+    }
+    Ok(stored)
+}
+
+/// Fails unless the account `name` exists, or adds it with `new_accounts`.
+fn check_account(conn: &Connection, name: &str, new_accounts: bool) -> Result<(), LindaError> {
+    let known: bool = conn.query_row("SELECT COUNT(*) > 0 FROM account WHERE name = ?1", [name], |row| row.get(0))?;
+    match known || new_accounts {
+        true => conn.execute(Tr::INSERT_ACCOUNT, [name])?,
+        false => return Err(LindaError::UnknownAccount(name.to_string())),
+    };
+    Ok(())
+}
+
+/// Describes how each command was read and what it would write, touching no
+/// database.
+pub fn dry_run(cmds: &[Cmd], base: &str) -> Result<Vec<String>, LindaError> {
+    let mut lines = Vec::new();
+    for cmd in cmds {
+        lines.push(format!("pack: {:?}", cmd.pack));
+        lines.push(format!("kind: {:?}", cmd.kind_of()?));
+        for entry in Entry::of(cmd.clone(), base)? {
+            lines.extend(entry.sql().into_iter().map(|sql| format!("sql: {}", sql)));
+        }
+    }
+    Ok(lines)
+}
+
+/// Inserts an order and describes it along with its new id.
+fn insert_tr(conn: &Connection, transaction: &Tr, stored: &mut Stored) -> Result<(), LindaError> {
+    let id = transaction.insert(conn)?;
+    stored.debug.push(format!("{:?}", transaction));
+    stored.rows.push(Inserted { id, kind: transaction.kind.to_string() });
+    stored.lines.push(format!("Recorded {}", transaction.describe(id)));
+    if transaction.kind == OrderKind::Expense {
+        stored.warnings.extend(over_budget(conn, transaction)?);
+    }
+    Ok(())
+}
+
+/// What `undo` took back: a line describing each row, and the command that
+/// would enter them again, along with the `--account` to give it when the
+/// command can't name the account itself.
+#[derive(Debug)]
+pub struct Undone {
+    pub lines: Vec<String>,
+    pub text: String,
+    pub account: Option<String>,
+}
+
+/// Deletes every row the last command wrote, transactions like `delete`.
+/// After an `import` that is the whole file.
+pub fn undo(db: &Path) -> Result<Undone, LindaError> {
+    undo_batch(&mut open(db)?)
+}
+
+fn undo_batch(conn: &mut Connection) -> Result<Undone, LindaError> {
+    // Taken before reading, so no command gets in before the rows go.
+    let tx = conn.transaction_with_behavior(TransactionBehavior::Immediate)?;
+    let batch: Option<i64> = tx.query_row(
+        "SELECT MAX(batch) FROM (SELECT batch FROM `transaction` WHERE deleted_at IS NULL UNION ALL SELECT batch FROM transfer)",
+        [],
+        |row| row.get(0),
+    )?;
+    let batch = batch.ok_or(LindaError::NothingToUndo)?;
+    let records = Record::query(&tx, "WHERE batch = ?1 AND deleted_at IS NULL ORDER BY id", [batch])?;
+    let transfers = Transfer::of_batch(&tx, batch)?;
+    tx.execute(
+        "UPDATE `transaction` SET deleted_at = ?1 WHERE batch = ?2 AND deleted_at IS NULL",
+        params![Local::now().timestamp(), batch],
+    )?;
+    tx.execute("DELETE FROM transfer WHERE batch = ?1", [batch])?;
+    tx.commit()?;
+
+    let mut lines = Vec::new();
+    let mut cmds = Vec::new();
+    for record in &records {
+        lines.push(record.tr.describe(record.id));
+        cmds.push(record.tr.to_cmd());
+    }
+    for (id, transfer) in &transfers {
+        lines.push(transfer.describe(*id));
+        cmds.push(transfer.to_cmd());
+    }
+    // One command books to one account, so the first order speaks for all.
+    let account = records.first().map(|record| record.tr.account.clone())
+        .filter(|account| account != DEFAULT_ACCOUNT && !is_account_name(account));
+    Ok(Undone { lines, text: cmds.join(&CMD_SEP.to_string()), account })
+}
+
+/// A warning if the month of an expense now spends more on its category than
+/// the budget allows. The expense is kept either way.
+fn over_budget(conn: &Connection, transaction: &Tr) -> Result<Option<String>, LindaError> {
+    let limit: Option<i64> = conn.query_row(
+        "SELECT monthly_limit FROM budget WHERE category = ?1",
+        [&transaction.category],
+        |row| row.get(0),
+    ).optional()?;
+    let Some(limit) = limit else { return Ok(None) };
+    let day = transaction.created_at.date_naive();
+    let first = day.with_day(1).unwrap_or(day);
+    let last = first.checked_add_months(chrono::Months::new(1)).and_then(|d| d.pred_opt()).unwrap_or(day);
+    let month = Period::new(Some(first), Some(last));
+    let spent: i64 = conn.query_row(
+        "SELECT COALESCE(SUM(CASE kind WHEN ?2 THEN -tax ELSE tax END), 0) FROM `transaction`
+         WHERE category_id = (SELECT id FROM category WHERE name = ?1) AND kind IN (?2, ?3)
+           AND created_at >= ?4 AND created_at < ?5 AND deleted_at IS NULL",
+        params![transaction.category, OrderKind::Refund.to_string(), OrderKind::Expense.to_string(), month.since, month.until],
+        |row| row.get(0),
+    )?;
+    Ok((spent > limit).then(|| format!(
+        "{} is over its budget for {}: {} of {}",
+        transaction.category, first.format("%Y-%m"), format_cents(spent), format_cents(limit)
+    )))
+}
+
+/// Sets the monthly spending limit of a category.
+pub fn budget(db: &Path, category: &str, limit: i64) -> Result<(), LindaError> {
+    let conn = open(db)?;
+    conn.execute(
+        "INSERT OR REPLACE INTO budget (category, monthly_limit) VALUES (?1, ?2)",
+        params![category, limit],
+    )?;
+    Ok(())
+}
+
+/// Moves every transaction of category `from` to `to`, merging the two when
+/// `to` is in use already. A budget goes along unless `to` has its own.
+/// Returns the number of transactions moved.
+pub fn rename_category(db: &Path, from: &str, to: &str) -> Result<usize, LindaError> {
+    let mut conn = open(db)?;
+    let tx = conn.transaction_with_behavior(TransactionBehavior::Immediate)?;
+    let known: bool = tx.query_row(
+        "SELECT EXISTS (SELECT 1 FROM category WHERE name = ?1) OR EXISTS (SELECT 1 FROM budget WHERE category = ?1)",
+        [from],
+        |row| row.get(0),
+    )?;
+    if !known {
+        return Err(LindaError::UnknownCategory(from.to_string()));
+    }
+    tx.execute(Tr::INSERT_CATEGORY, [to])?;
+    let renamed = tx.execute(
+        "UPDATE `transaction` SET category_id = (SELECT id FROM category WHERE name = ?1)
+         WHERE category_id = (SELECT id FROM category WHERE name = ?2)",
+        [to, from],
+    )?;
+    tx.execute("DELETE FROM category WHERE name = ?1 AND ?1 != ?2", [from, to])?;
+    tx.execute("UPDATE OR IGNORE budget SET category = ?1 WHERE category = ?2", [to, from])?;
+    tx.execute("DELETE FROM budget WHERE category = ?1 AND ?1 != ?2", [from, to])?;
+    tx.commit()?;
+    Ok(renamed)
+}
+
+/// Defines `name` as a shorthand for the command `template`, replacing an
+/// older one. The template is stored as it reads back, so that it expands the
+/// same under any separator, and is returned that way. Its `@` fields are
+/// kept as written, a relative date stays relative.
+pub fn shorthand(db: &Path, name: &str, template: &str, syntax: &Syntax) -> Result<String, LindaError> {
+    let name = name.to_lowercase();
+    let reason = if name.is_empty() || !name.chars().all(char::is_alphabetic) {
+        Some("the name must be a word of letters")
+    } else if mod_of_word(&name).is_some() {
+        Some("the name spells out a modifier")
+    } else if !template.trim_start().starts_with(MODS) {
+        Some("the template must start with a modifier, it can't be another shorthand or have a date")
+    } else {
+        None
+    };
+    if let Some(reason) = reason {
+        return Err(LindaError::BadShorthand(name, String::from(reason)));
+    }
+    let cmd = parse_batch(template, syntax).map_err(|e| LindaError::BadShorthand(name.clone(), e.reason))?;
+    let [cmd] = <[Cmd; 1]>::try_from(cmd)
+        .map_err(|_| LindaError::BadShorthand(name.clone(), String::from("the template must be a single command")))?;
+    let template = template.trim_start();
+    let ch = template.chars().next().unwrap_or(SEP);
+    let fields = split_fields(&template[ch.len_utf8()..], syntax.sep, 1)
+        .map_err(|e| LindaError::BadShorthand(name.clone(), e.to_string()))?;
+    let at_fields = fields.into_iter().filter(|(f, quoted, _)| !quoted && f.starts_with('@')).map(|(f, _, _)| f);
+    let template = std::iter::once(cmd.to_string()).chain(at_fields).collect::<Vec<_>>().join(&SEP.to_string());
+    open(db)?.execute(
+        "INSERT OR REPLACE INTO shorthand (name, template) VALUES (?1, ?2)",
+        params![name, template],
+    )?;
+    Ok(template)
+}
+
+/// All shorthands, none for a database that doesn't exist or isn't set up
+/// yet. The database is only read, so that `--dry-run` leaves it alone.
+pub fn shorthands(db: &Path) -> Result<HashMap<String, String>, LindaError> {
+    if !db.exists() {
+        return Ok(HashMap::new());
+    }
+    let conn = connect_read_only(db)?;
+    let has_table: bool = conn.query_row(
+        "SELECT COUNT(*) > 0 FROM sqlite_master WHERE type = 'table' AND name = 'shorthand'",
+        [],
+        |row| row.get(0),
+    )?;
+    if !has_table {
+        return Ok(HashMap::new());
+    }
+    let mut stmt = conn.prepare("SELECT name, template FROM shorthand")?;
+    let rows = stmt.query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?;
+    Ok(rows.collect::<Result<_>>()?)
+}
+
+/// The newest `limit` transactions in `period`, newest first, of one
+/// account or of all of them. Deleted ones are left out unless
+/// `include_deleted`.
+pub fn list(
+    db: &Path, period: Period, account: Option<&str>, limit: u32, include_deleted: bool,
+) -> Result<Vec<Record>, LindaError> {
+    let conn = open(db)?;
+    check_timestamps(&conn)?;
+    Ok(Record::select(&conn, period, account, limit, include_deleted)?)
+}
+
+/// Lays `rows` out in columns as wide as their widest cell, under `header`
+/// and a rule. The columns listed in `right` are aligned to the right, for
+/// numbers. Widths count chars, so wide characters like emoji still push the
+/// rest of their row a little.
+pub fn render_table(header: &[&str], right: &[usize], rows: &[Vec<String>]) -> Vec<String> {
+    let mut widths: Vec<usize> = header.iter().map(|h| h.chars().count()).collect();
+    for row in rows {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = (*width).max(cell.chars().count());
+        }
+    }
+    let line = |cells: Vec<&str>| cells.iter().enumerate()
+        .map(|(i, cell)| if right.contains(&i) {
+            format!("{:>1$}", cell, widths[i])
+        } else {
+            format!("{:<1$}", cell, widths[i])
+        })
+        .collect::<Vec<_>>()
+        .join("  ")
+        .trim_end()
+        .to_string();
+    let rule: Vec<String> = widths.iter().map(|w| "-".repeat(*w)).collect();
+    let mut lines = vec![line(header.to_vec()), line(rule.iter().map(String::as_str).collect())];
+    lines.extend(rows.iter().map(|row| line(row.iter().map(String::as_str).collect())));
+    lines
+}
+
+pub enum Style {
+    Green,
+    Red,
+    Bold,
+}
+
+/// Whether to color output: only on a terminal, and not if `NO_COLOR` is set
+/// to anything but an empty string.
+pub fn use_color() -> bool {
+    let no_color = std::env::var_os("NO_COLOR").is_some_and(|v| !v.is_empty());
+    !no_color && std::io::stdout().is_terminal()
+}
+
+/// Wraps `text` in the escape codes of `style` if `color`, see `use_color`.
+pub fn paint(text: &str, style: Style, color: bool) -> String {
+    if !color {
+        return text.to_string();
+    }
+    let code = match style {
+        Style::Green => 32,
+        Style::Red => 31,
+        Style::Bold => 1,
+    };
+    format!("\x1b[{}m{}\x1b[0m", code, text)
+}
+
+/// The transactions of `ids`, to show before deleting them. An id that isn't
+/// there, or is deleted already, is an error.
+pub fn to_delete(db: &Path, ids: &[i64]) -> Result<Vec<Record>, LindaError> {
+    find_undeleted(&open(db)?, ids)
+}
+
+fn find_undeleted(conn: &Connection, ids: &[i64]) -> Result<Vec<Record>, LindaError> {
+    let mut records = Vec::new();
+    for (i, &id) in ids.iter().enumerate() {
+        if ids[..i].contains(&id) {
+            continue;
+        }
+        let record = Record::query(conn, "WHERE id = ?1 AND deleted_at IS NULL", [id])?
+            .pop()
+            .ok_or(LindaError::NotFound(id))?;
+        records.push(record);
+    }
+    Ok(records)
+}
+
+/// Marks transactions as deleted, which leaves them out of everything but
+/// `list --include-deleted` until they are restored or purged. They go in one
+/// SQL transaction, an id that isn't there deletes none of them.
+pub fn delete(db: &Path, ids: &[i64]) -> Result<(), LindaError> {
+    delete_rows(&mut open(db)?, ids)
+}
+
+fn delete_rows(conn: &mut Connection, ids: &[i64]) -> Result<(), LindaError> {
+    let tx = conn.transaction_with_behavior(TransactionBehavior::Immediate)?;
+    for (i, &id) in ids.iter().enumerate() {
+        if !ids[..i].contains(&id) {
+            delete_row(&tx, id)?;
+        }
+    }
+    tx.commit()?;
+    Ok(())
+}
+
+fn delete_row(conn: &Connection, id: i64) -> Result<(), LindaError> {
+    let deleted = conn.execute(
+        "UPDATE `transaction` SET deleted_at = ?1 WHERE id = ?2 AND deleted_at IS NULL",
+        params![Local::now().timestamp(), id],
+    )?;
+    if deleted == 0 {
+        return Err(LindaError::NotFound(id));
+    }
+    Ok(())
+}
+
+pub fn restore(db: &Path, id: i64) -> Result<(), LindaError> {
+    restore_row(&open(db)?, id)
+}
+
+fn restore_row(conn: &Connection, id: i64) -> Result<(), LindaError> {
+    let deleted_at: Option<Option<i64>> = conn.query_row(
+        "SELECT deleted_at FROM `transaction` WHERE id = ?1",
+        [id],
+        |row| row.get(0),
+    ).optional()?;
+    match deleted_at {
+        None => return Err(LindaError::NotFound(id)),
+        Some(None) => return Err(LindaError::NotDeleted(id)),
+        Some(Some(_)) => (),
+    }
+    conn.execute("UPDATE `transaction` SET deleted_at = NULL WHERE id = ?1", [id])?;
+    Ok(())
+}
+
+/// Removes the deleted transactions for good and returns how many there
+/// were.
+pub fn purge(db: &Path) -> Result<usize, LindaError> {
+    purge_rows(&mut open(db)?)
+}
+
+fn purge_rows(conn: &mut Connection) -> Result<usize, LindaError> {
+    let tx = conn.transaction_with_behavior(TransactionBehavior::Immediate)?;
+    tx.execute(
+        "DELETE FROM transaction_tag WHERE transaction_id IN (SELECT id FROM `transaction` WHERE deleted_at IS NOT NULL)",
+        (),
+    )?;
+    let purged = tx.execute("DELETE FROM `transaction` WHERE deleted_at IS NOT NULL", ())?;
+    tx.commit()?;
+    Ok(purged)
+}
+
+/// What `edit` changes in a transaction, the fields left `None` stay as they
+/// are. An empty description removes it.
+#[derive(Debug, Default)]
+pub struct Changes {
+    pub kind: Option<OrderKind>,
+    pub tax: Option<i64>,
+    pub category: Option<String>,
+    pub description: Option<String>,
+    pub created_at: Option<DateTime<Local>>,
+}
+
+impl Changes {
+    pub fn is_empty(&self) -> bool {
+        self.kind.is_none() && self.tax.is_none() && self.category.is_none()
+            && self.description.is_none() && self.created_at.is_none()
+    }
+    /// A line like `amount: 120.00 USD -> 3.50 USD` for each field of `tr`
+    /// the changes make different.
+    fn diff(&self, tr: &Tr) -> Vec<String> {
+        let amount = |tax: i64| format!("{} {}", format_cents(tax), tr.currency);
+        let description = |description: Option<&str>| format!("'{}'", description.unwrap_or_default());
+        let date = |created_at: DateTime<Local>| created_at.format("%Y-%m-%d %H:%M").to_string();
+        let fields = [
+            ("kind", tr.kind.to_string(), self.kind.map(|kind| kind.to_string())),
+            ("amount", amount(tr.tax), self.tax.map(amount)),
+            ("category", tr.category.clone(), self.category.clone()),
+            ("description", description(tr.description.as_deref()), self.description.as_deref().map(|d| description(Some(d)))),
+            ("date", date(tr.created_at), self.created_at.map(date)),
+        ];
+        fields.into_iter()
+            .filter_map(|(name, old, new)| new.filter(|new| *new != old).map(|new| format!("{}: {} -> {}", name, old, new)))
+            .collect()
+    }
+}
+
+/// What editing transaction `id` would change, to show before doing it. An
+/// id that isn't there, or is deleted, is an error.
+pub fn edit_diff(db: &Path, id: i64, changes: &Changes) -> Result<Vec<String>, LindaError> {
+    if changes.is_empty() {
+        return Err(LindaError::NothingToChange);
+    }
+    let record = find_undeleted(&open(db)?, &[id])?.remove(0);
+    Ok(changes.diff(&record.tr))
+}
+
+/// Updates only the fields that were given and returns the transaction as it
+/// is now.
+pub fn edit(db: &Path, id: i64, changes: &Changes) -> Result<Record, LindaError> {
+    edit_row(&mut open(db)?, id, changes)
+}
+
+fn edit_row(conn: &mut Connection, id: i64, changes: &Changes) -> Result<Record, LindaError> {
+    if changes.is_empty() {
+        return Err(LindaError::NothingToChange);
+    }
+    let tx = conn.transaction_with_behavior(TransactionBehavior::Immediate)?;
+    if let Some(category) = &changes.category {
+        tx.execute(Tr::INSERT_CATEGORY, [category])?;
+    }
+    let updated = tx.execute(
+        "UPDATE `transaction` SET kind = COALESCE(?1, kind), tax = COALESCE(?2, tax),
+           category_id = COALESCE((SELECT id FROM category WHERE name = ?3), category_id),
+           description = CASE WHEN ?4 IS NULL THEN description ELSE NULLIF(?4, '') END,
+           created_at = COALESCE(?5, created_at)
+         WHERE id = ?6 AND deleted_at IS NULL",
+        params![
+            changes.kind.map(|kind| kind.to_string()), changes.tax, changes.category, changes.description,
+            changes.created_at.map(|created_at| created_at.timestamp()), id,
+        ],
+    )?;
+    if updated == 0 {
+        return Err(LindaError::NotFound(id));
+    }
+    let record = Record::query(&tx, "WHERE id = ?1", [id])?.remove(0);
+    tx.commit()?;
+    Ok(record)
+}
+
+#[derive(Debug, Serialize)]
+pub struct Totals {
+    pub currency: String,
+    pub income: i64,
+    pub expense: i64,
+    pub net: i64,
+}
+
+pub fn report(db: &Path, period: Period, account: Option<&str>, base: &str) -> Result<Vec<Totals>, LindaError> {
+    let conn = open(db)?;
+    check_timestamps(&conn)?;
+    totals(&conn, period, account, base)
+}
+
+/// Income, expenses less refunds and net within the period, of one account
+/// or of all, apart for each currency. With nothing recorded it's all zero in
+/// `base`.
+fn totals(conn: &Connection, period: Period, account: Option<&str>, base: &str) -> Result<Vec<Totals>, LindaError> {
+    let mut stmt = conn.prepare(
+        "SELECT currency,
+           SUM(CASE kind WHEN ?1 THEN tax ELSE 0 END),
+           SUM(CASE kind WHEN ?2 THEN tax WHEN ?3 THEN -tax ELSE 0 END)
+         FROM `transaction`
+         WHERE created_at >= ?4 AND created_at < ?5 AND (?6 IS NULL OR account_id = (SELECT id FROM account WHERE name = ?6))
+           AND deleted_at IS NULL
+         GROUP BY currency ORDER BY currency",
+    )?;
+    let mut totals = stmt.query_map(
+        params![
+            OrderKind::Income.to_string(), OrderKind::Expense.to_string(), OrderKind::Refund.to_string(),
+            period.since, period.until, account,
+        ],
+        |row| {
+            let (income, expense): (i64, i64) = (row.get(1)?, row.get(2)?);
+            Ok(Totals { currency: row.get(0)?, income, expense, net: income - expense })
+        },
+    )?.collect::<Result<Vec<_>>>()?;
+    if totals.is_empty() {
+        totals.push(Totals { currency: String::from(base), income: 0, expense: 0, net: 0 });
+    }
+    Ok(totals)
+}
+
+/// Fails on a transaction whose `created_at` isn't a number, which would
+/// otherwise fall outside of every period without a word.
+fn check_timestamps(conn: &Connection) -> Result<(), LindaError> {
+    let bad: Option<(i64, String)> = conn.query_row(
+        "SELECT id, CAST(created_at AS TEXT) FROM `transaction` WHERE typeof(created_at) != 'integer' LIMIT 1",
+        [],
+        |row| Ok((row.get(0)?, row.get(1)?)),
+    ).optional()?;
+    match bad {
+        Some((id, text)) => Err(LindaError::BadTimestamp(id, text)),
+        None => Ok(()),
+    }
+}
+
+/// Totals are not converted between currencies, so at least say when they
+/// add up amounts in different ones.
+fn mixed_currencies(conn: &Connection, period: Period, account: Option<&str>) -> Result<Option<String>, LindaError> {
+    let mut stmt = conn.prepare(
+        "SELECT DISTINCT currency FROM `transaction`
+         WHERE created_at >= ?1 AND created_at < ?2 AND (?3 IS NULL OR account_id = (SELECT id FROM account WHERE name = ?3))
+           AND deleted_at IS NULL
+         ORDER BY currency",
+    )?;
+    let currencies = stmt.query_map(params![period.since, period.until, account], |row| row.get(0))?
+        .collect::<Result<Vec<String>>>()?;
+    Ok((currencies.len() > 1).then(|| format!("the totals mix amounts in {} without converting them", currencies.join(", "))))
+}
+
+/// The net of every income, expense and refund ever recorded.
+#[derive(Debug, Serialize)]
+pub struct Balance {
+    pub balance: i64,
+    #[serde(skip)]
+    pub warnings: Vec<String>,
+}
+
+/// The balance of a single account also counts the transfers in and out of
+/// it.
+pub fn balance(db: &Path, account: Option<&str>) -> Result<Balance, LindaError> {
+    let conn = open(db)?;
+    let warnings = mixed_currencies(&conn, Period::new(None, None), account)?.into_iter().collect();
+    let orders: i64 = conn.query_row(
+        "SELECT COALESCE(SUM(CASE kind WHEN ?1 THEN -tax ELSE tax END), 0) FROM `transaction`
+         WHERE (?2 IS NULL OR account_id = (SELECT id FROM account WHERE name = ?2)) AND deleted_at IS NULL",
+        params![OrderKind::Expense.to_string(), account],
+        |row| row.get(0),
+    )?;
+    let transfers: i64 = conn.query_row(
+        "SELECT COALESCE(SUM(CASE ?1 WHEN target THEN tax WHEN source THEN -tax ELSE 0 END), 0) FROM transfer",
+        [account],
+        |row| row.get(0),
+    )?;
+    Ok(Balance { balance: orders + transfers, warnings })
+}
+
+#[derive(Debug, Serialize)]
+pub struct AccountTotal {
+    pub account: String,
+    pub count: i64,
+    pub balance: i64,
+}
+
+/// Adds an account for commands to book to. False if there already is one by
+/// that name.
+pub fn new_account(db: &Path, name: &str) -> Result<bool, LindaError> {
+    Ok(open(db)?.execute(Tr::INSERT_ACCOUNT, [name])? > 0)
+}
+
+/// Each account with its number of transactions and its balance, counting
+/// the transfers in and out of it.
+pub fn accounts(db: &Path) -> Result<Vec<AccountTotal>, LindaError> {
+    let conn = open(db)?;
+    let mut stmt = conn.prepare(
+        "SELECT a.name, COUNT(t.id), COALESCE(SUM(CASE t.kind WHEN ?1 THEN -t.tax ELSE t.tax END), 0)
+           + (SELECT COALESCE(SUM(CASE a.name WHEN target THEN tax ELSE -tax END), 0) FROM transfer
+              WHERE a.name IN (source, target))
+         FROM account a LEFT JOIN `transaction` t ON t.account_id = a.id AND t.deleted_at IS NULL
+         GROUP BY a.id ORDER BY a.name",
+    )?;
+    let rows = stmt.query_map(
+        [OrderKind::Expense.to_string()],
+        |row| Ok(AccountTotal { account: row.get(0)?, count: row.get(1)?, balance: row.get(2)? }),
+    )?.collect::<Result<Vec<_>>>()?;
+    Ok(rows)
+}
+
+/// What a category adds up to. Income and expenses are kept apart, since a
+/// category can have both and their net says little; refunds are taken off
+/// the expenses.
+#[derive(Debug, Serialize)]
+pub struct CategoryTotal {
+    pub category: String,
+    pub count: i64,
+    pub income: i64,
+    pub expense: i64,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(transparent)]
+pub struct Categories {
+    pub rows: Vec<CategoryTotal>,
+    #[serde(skip)]
+    pub warnings: Vec<String>,
+}
+
+/// How many transactions each category has and what they add up to. Sorted
+/// by name, or by the amount moved either way, largest first.
+pub fn categories(db: &Path, by_total: bool) -> Result<Categories, LindaError> {
+    let conn = open(db)?;
+    let warnings = mixed_currencies(&conn, Period::new(None, None), None)?.into_iter().collect();
+    let order = if by_total { "income + expense DESC, category" } else { "category" };
+    let mut stmt = conn.prepare(&format!(
+        "SELECT c.name AS category, COUNT(*),
+           COALESCE(SUM(CASE kind WHEN ?1 THEN tax END), 0) AS income,
+           COALESCE(SUM(CASE kind WHEN ?2 THEN tax WHEN ?3 THEN -tax END), 0) AS expense
+         FROM `transaction` t JOIN category c ON c.id = t.category_id WHERE deleted_at IS NULL
+         GROUP BY c.id ORDER BY {}", order
+    ))?;
+    let rows = stmt.query_map(
+        [OrderKind::Income.to_string(), OrderKind::Expense.to_string(), OrderKind::Refund.to_string()],
+        |row| Ok(CategoryTotal { category: row.get(0)?, count: row.get(1)?, income: row.get(2)?, expense: row.get(3)? }),
+    )?.collect::<Result<Vec<_>>>()?;
+    Ok(Categories { rows, warnings })
+}
+
+#[derive(Debug, Serialize)]
+pub struct Stats {
+    pub kind: OrderKind,
+    pub currency: String,
+    pub count: i64,
+    pub min: i64,
+    pub max: i64,
+    pub average: i64,
+}
+
+/// The number, smallest, largest and average amount of each kind of order in
+/// each currency, of one category if given. Averages are rounded to the
+/// nearest cent, halves away from zero. Empty if nothing matches.
+pub fn stats(db: &Path, category: Option<&str>, period: Period, account: Option<&str>) -> Result<Vec<Stats>, LindaError> {
+    let conn = open(db)?;
+    check_timestamps(&conn)?;
+    let mut stmt = conn.prepare(
+        "SELECT kind, currency, COUNT(*), MIN(tax), MAX(tax), SUM(tax) FROM `transaction`
+         WHERE created_at >= ?1 AND created_at < ?2 AND (?3 IS NULL OR category_id = (SELECT id FROM category WHERE name = ?3))
+           AND (?4 IS NULL OR account_id = (SELECT id FROM account WHERE name = ?4)) AND deleted_at IS NULL
+         GROUP BY kind, currency ORDER BY kind, currency",
+    )?;
+    let stats = stmt.query_map(params![period.since, period.until, category, account], |row| {
+        let (count, sum): (i64, i64) = (row.get(2)?, row.get(5)?);
+        Ok(Stats {
+            kind: row.get(0)?,
+            currency: row.get(1)?,
+            count,
+            min: row.get(3)?,
+            max: row.get(4)?,
+            // The sum of i64s fits, so the average does too.
+            average: div_round(sum as i128, count as i128) as i64,
+        })
+    })?.collect::<Result<Vec<_>>>()?;
+    Ok(stats)
+}
+
+/// Copies the database with SQLite's backup API, which is safe while another
+/// process has it open, to `to` or else to a file next to it named after the
+/// time, like `linda-2024-05-03T101500.db`. An existing file isn't
+/// overwritten. Returns where the copy is and how many transactions it has.
+pub fn backup(db: &Path, to: Option<&Path>) -> Result<(PathBuf, i64), LindaError> {
+    // Opening would create an empty file, and back that up.
+    if !db.exists() {
+        return Err(LindaError::NotInitialized(db.to_path_buf()));
+    }
+    let to = to.map(Path::to_path_buf).unwrap_or_else(|| {
+        let stem = db.file_stem().and_then(|s| s.to_str()).unwrap_or("linda");
+        db.with_file_name(format!("{}-{}.db", stem, Local::now().format("%Y-%m-%dT%H%M%S")))
+    });
+    if to.exists() {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::AlreadyExists, format!("{} already exists", to.display()),
+        ).into());
+    }
+    // Only read, a backup shouldn't upgrade what it copies.
+    let conn = connect_read_only(db)?;
+    conn.backup(rusqlite::DatabaseName::Main, &to, None)?;
+    // A backup is one file, with no log to keep next to it.
+    Connection::open(&to)?.query_row("PRAGMA journal_mode = DELETE", [], |_| Ok(()))?;
+    let count = count_transactions(&to)?;
+    Ok((to, count))
+}
+
+/// What a database holds, to say what restoring a backup would change.
+#[derive(Debug)]
+pub struct Summary {
+    pub version: usize,
+    pub count: i64,
+    pub first: Option<DateTime<Local>>,
+    pub last: Option<DateTime<Local>>,
+}
+
+impl Summary {
+    fn of(conn: &Connection) -> Result<Summary, LindaError> {
+        let version = version_of(conn)?.unwrap_or(0);
+        let (count, first, last): (i64, Option<i64>, Option<i64>) = conn.query_row(
+            "SELECT COUNT(*), MIN(created_at), MAX(created_at) FROM `transaction`",
+            [],
+            |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)),
+        )?;
+        Ok(Summary { version, count, first: first.and_then(from_timestamp), last: last.and_then(from_timestamp) })
+    }
+}
+
+impl fmt::Display for Summary {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} transaction(s)", self.count)?;
+        if let (Some(first), Some(last)) = (self.first, self.last) {
+            write!(f, " dated {}..{}", first.format("%Y-%m-%d"), last.format("%Y-%m-%d"))?;
+        }
+        Ok(())
+    }
+}
+
+/// Checks that the file at `path` is an intact linda database that may
+/// replace `db`, and sums both up. A database at a newer schema than the
+/// backup is only to be replaced with `force`, one that can't be read is
+/// what backups are for.
+pub fn check_backup(
+    db: &Path, path: &Path, force: bool,
+) -> Result<(Summary, Option<Result<Summary, LindaError>>), LindaError> {
+    let bad = |reason: String| LindaError::BadBackup(path.to_path_buf(), reason);
+    if !path.is_file() {
+        return Err(bad(String::from("there is no such file")));
+    }
+    let source = connect_read_only(path)?;
+    let check: String = source.query_row("PRAGMA quick_check", [], |row| row.get(0))
+        .map_err(|e| bad(e.to_string()))?;
+    if check != "ok" {
+        return Err(bad(check));
+    }
+    let backup = Summary::of(&source).map_err(|_| bad(String::from("it isn't a linda database")))?;
+    if backup.version > SCHEMA_VERSION {
+        return Err(bad(LindaError::NewerSchema(backup.version).to_string()));
+    }
+    let current = db.exists().then(|| connect_read_only(db).and_then(|conn| Summary::of(&conn)));
+    if let Some(Ok(current)) = &current {
+        if current.version > backup.version && !force {
+            return Err(bad(format!(
+                "it is at schema version {} and the database at {}, use --force to go back",
+                backup.version, current.version
+            )));
+        }
+    }
+    Ok((backup, current))
+}
+
+/// Replaces the database with the backup at `path`, once `check_backup`
+/// is happy with it. The backup is copied next to the database and renamed
+/// over it, so the database is never left half written.
+pub fn restore_backup(db: &Path, path: &Path, force: bool) -> Result<(), LindaError> {
+    check_backup(db, path, force)?;
+    if let Some(dir) = db.parent() {
+        std::fs::create_dir_all(dir)?;
+    }
+    let source = connect_read_only(path)?;
+    let temp = db.with_extension("restoring");
+    if let Err(e) = source.backup(rusqlite::DatabaseName::Main, &temp, None) {
+        let _ = std::fs::remove_file(&temp);
+        return Err(e.into());
+    }
+    Connection::open(&temp)?.query_row("PRAGMA journal_mode = DELETE", [], |_| Ok(()))?;
+    remove_wal(db)?;
+    std::fs::rename(&temp, db)?;
+    Ok(())
+}
+
+/// What `maintenance` did: the file size in bytes before and after, and the
+/// rows in each table.
+#[derive(Debug)]
+pub struct Maintained {
+    pub before: u64,
+    pub after: u64,
+    pub tables: Vec<(String, i64)>,
+}
+
+/// Checks the database for corruption, then rebuilds it to reclaim the
+/// space of deleted rows and refreshes the statistics the query planner uses.
+pub fn maintenance(db: &Path) -> Result<Maintained, LindaError> {
+    let conn = open(db)?;
+    // The first few problems are enough to tell it's broken.
+    let problems = conn.prepare("PRAGMA integrity_check(5)")?
+        .query_map([], |row| row.get(0))?
+        .collect::<Result<Vec<String>>>()?;
+    if problems != ["ok"] {
+        return Err(LindaError::Corrupt(problems.join("; ")));
+    }
+    // Checkpointed so that the file alone holds everything, and shows the size.
+    let checkpoint = || conn.query_row("PRAGMA wal_checkpoint(TRUNCATE)", [], |_| Ok(()));
+    checkpoint()?;
+    let before = std::fs::metadata(db)?.len();
+    conn.execute_batch("VACUUM; ANALYZE;")?;
+    checkpoint()?;
+    let after = std::fs::metadata(db)?.len();
+    let names = conn.prepare("SELECT name FROM sqlite_master WHERE type = 'table' AND name NOT LIKE 'sqlite_%' ORDER BY name")?
+        .query_map([], |row| row.get(0))?
+        .collect::<Result<Vec<String>>>()?;
+    let mut tables = Vec::new();
+    for name in names {
+        let count = conn.query_row(&format!("SELECT COUNT(*) FROM `{}`", name), [], |row| row.get(0))?;
+        tables.push((name, count));
+    }
+    Ok(Maintained { before, after, tables })
+}
+
+/// Writes every transaction to `output`, or to stdout when it's not given.
+pub fn export(db: &Path, format: ExportFormat, output: Option<&Path>) -> Result<(), LindaError> {
+    let conn = open(db)?;
+    check_timestamps(&conn)?;
+    let out: Box<dyn std::io::Write> = match output {
+        Some(path) => Box::new(std::fs::File::create(path)?),
+        None => Box::new(std::io::stdout()),
+    };
+    match format {
+        ExportFormat::Csv => {
+            let mut writer = csv::Writer::from_writer(out);
+            writer.write_record(CSV_HEADER)?;
+            for record in Record::all(&conn)? {
+                let tr = record.tr;
+                writer.write_record([
+                    record.id.to_string(),
+                    tr.created_at.to_rfc3339(),
+                    format_cents(tr.tax),
+                    tr.category,
+                    tr.duration.to_string(),
+                    tr.description.unwrap_or_default(),
+                    tr.kind.to_string(),
+                    tr.currency,
+                    tr.account,
+                ])?;
+            }
+            writer.flush()?;
+        },
+    }
+    Ok(())
+}
+
+/// Inserts every row of the CSV file at `path`, or none of them if any row is
+/// bad or books to an account that doesn't exist unless `new_accounts`.
+/// Returns the number of inserted rows.
+pub fn import(db: &Path, path: &Path, syntax: &Syntax, new_accounts: bool) -> Result<usize, LindaError> {
+    let mut conn = open(db)?;
+    let mut reader = csv::Reader::from_path(path)?;
+    let header = reader.headers()?.clone();
+    // Exports made before accounts existed lack the last column.
+    if header.iter().ne(CSV_HEADER) && header.iter().ne(CSV_HEADER[..8].iter().copied()) {
+        return Err(ImportError::Header(header.iter().collect::<Vec<_>>().join(",")).into());
+    }
+    let tx = conn.transaction_with_behavior(TransactionBehavior::Immediate)?;
+    let mut count = 0;
+    for row in reader.records() {
+        let row = row?;
+        let line = row.position().map_or(0, |p| p.line());
+        let tr = Tr::from_csv(&row, syntax).map_err(|reason| ImportError::Row(line, reason))?;
+        check_account(&tx, &tr.account, new_accounts)?;
+        tr.insert(&tx)?;
+        count += 1;
+    }
+    // Undone all at once, like a single command.
+    set_batch(&tx, next_batch(&tx)?)?;
+    tx.commit()?;
+    Ok(count)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{env, process};
+
+    #[test]
+    fn empty_command() {
+        assert!(matches!(Cmd::from_str(""), Err(LindaError::Parse(ParseCmdError { kind: ParseErrorKind::Empty, .. }))));
+    }
+
+    #[test]
+    fn blank_command() {
+        assert!(matches!(Cmd::from_str(" \t\n"), Err(LindaError::Parse(ParseCmdError { kind: ParseErrorKind::Empty, .. }))));
+    }
+
+    #[test]
+    fn lone_modifier() {
+        assert!(matches!(Cmd::from_str("&"), Err(LindaError::Parse(ParseCmdError { kind: ParseErrorKind::NoFields, .. }))));
+        assert!(matches!(Cmd::from_str(" >  "), Err(LindaError::Parse(ParseCmdError { kind: ParseErrorKind::NoFields, .. }))));
+        assert!(matches!(Cmd::from_str("&,"), Err(LindaError::Parse(ParseCmdError { kind: ParseErrorKind::NoFields, .. }))));
+        assert!(matches!(Cmd::from_str("> , ,"), Err(LindaError::Parse(ParseCmdError { kind: ParseErrorKind::NoFields, .. }))));
+    }
+
+    #[test]
+    fn errors_point_at_the_token() {
+        let at = |text| match Cmd::from_str(text) {
+            Err(LindaError::Parse(e)) => (e.kind, e.pos, e.token),
+            other => panic!("{:?} parsed as {:?}", text, other),
+        };
+        assert_eq!(at("&lunch,100"), (ParseErrorKind::BadAmount, 1, String::from("lunch")));
+        assert_eq!(at(">12,food,~1h"), (ParseErrorKind::BadDuration, 9, String::from("~1h")));
+        assert_eq!(at("@2024-05-03 >abc,food"), (ParseErrorKind::BadAmount, 13, String::from("abc")));
+        assert_eq!(at("@someday >3,food"), (ParseErrorKind::BadDate, 1, String::from("someday")));
+        assert_eq!(at("  %5,food"), (ParseErrorKind::UnexpectedMod, 0, String::from("%")));
+        assert_eq!(at(r#">5,"food"x"#), (ParseErrorKind::AfterQuote, 9, String::from("x")));
+        assert_eq!(Cmd::from_str(">abc,food").unwrap_err().to_string(), "unexpected token 'abc' at position 1: expected an amount like 12, 12.50 or 1.2k");
+        assert_eq!(Cmd::from_str(">").unwrap_err().to_string(), "expected fields after the modifier at position 1");
+        assert_eq!(Cmd::from_str("").unwrap_err().to_string(), "the command line is empty");
+    }
+
+    #[test]
+    fn expenses_are_stored_apart() {
+        let conn = memory_db();
+        for text in ["&1500,salary", ">500,rent"] {
+            Tr::new(Cmd::from_str(text).unwrap(), BASE_CURRENCY).unwrap().insert(&conn).unwrap();
+        }
+        let rows: Vec<(String, i32)> = conn.prepare("SELECT kind, tax FROM `transaction` ORDER BY id").unwrap()
+            .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))
+            .unwrap()
+            .collect::<Result<_>>()
+            .unwrap();
+        assert_eq!(rows, [(String::from("income"), 150000), (String::from("expense"), 50000)]);
+    }
+
+    #[test]
+    fn decimal_amounts_are_cents() {
+        for (text, cents) in [("100", 10000), ("12.50", 1250), ("12.5", 1250), ("12.", 1200), (".5", 50), ("0.05", 5)] {
+            assert_eq!(parse_cents(text).unwrap(), Some(cents), "{}", text);
+        }
+        assert!(matches!(parse_cents("1.234"), Err(ParseCmdError { kind: ParseErrorKind::BadAmount, token: a, .. }) if a == "1.234"));
+        assert!(matches!(parse_cents("1.2.3"), Err(ParseCmdError { kind: ParseErrorKind::BadAmount, .. })));
+        assert_eq!(parse_cents("lunch").unwrap(), None);
+        assert_eq!((format_cents(1250), format_cents(5), format_cents(-100)), (String::from("12.50"), String::from("0.05"), String::from("-1.00")));
+    }
+
+    #[test]
+    fn decimal_commands_store_cents() {
+        let tr = Tr::new(Cmd::from_str("&19.99,10,lunch").unwrap(), BASE_CURRENCY).unwrap();
+        assert_eq!((tr.tax, tr.duration, tr.category.as_str()), (1999, 10, "lunch"));
+        assert!(matches!(Cmd::from_str(">1.234,tea"), Err(LindaError::Parse(ParseCmdError { kind: ParseErrorKind::BadAmount, pos: 1, .. }))));
+    }
+
+    #[test]
+    fn amounts_take_k_and_m_suffixes() {
+        for (text, cents) in [("120k", 12_000_000), ("1.2m", 120_000_000), ("1.2M", 120_000_000), ("2.5K", 250_000), (".5k", 50_000)] {
+            assert_eq!(parse_cents(text).unwrap(), Some(cents), "{}", text);
+        }
+        for text in ["100x", "1k5"] {
+            assert!(matches!(parse_cents(text), Err(ParseCmdError { kind: ParseErrorKind::BadAmount, token: a, .. }) if a == text), "{}", text);
+        }
+        assert_eq!((parse_cents("k").unwrap(), parse_cents("kilo").unwrap(), parse_cents("k1").unwrap()), (None, None, None));
+        let tr = Tr::new(Cmd::from_str("&120k,salary").unwrap(), BASE_CURRENCY).unwrap();
+        assert_eq!((tr.tax, tr.category.as_str()), (12_000_000, "salary"));
+    }
+
+    #[test]
+    fn large_amounts_are_not_words() {
+        let tr = Tr::new(Cmd::from_str("&3000000000,house").unwrap(), BASE_CURRENCY).unwrap();
+        assert_eq!((tr.tax, tr.category.as_str()), (300_000_000_000, "house"));
+        assert_eq!(parse_cents("30m").unwrap(), Some(3_000_000_000));
+        for text in ["99999999999999999999", "100000000000000000m", "9000000000*9000000000"] {
+            assert!(matches!(parse_cents(text), Err(ParseCmdError { kind: ParseErrorKind::AmountOutOfRange, token: a, .. }) if a == text), "{}", text);
+        }
+        let conn = memory_db();
+        Tr::new(Cmd::from_str(">12.34,tea").unwrap(), BASE_CURRENCY).unwrap().insert(&conn).unwrap();
+        tr.insert(&conn).unwrap();
+        let taxes: Vec<i64> = conn.prepare("SELECT tax FROM `transaction` ORDER BY id").unwrap()
+            .query_map([], |row| row.get(0))
+            .unwrap()
+            .collect::<Result<_>>()
+            .unwrap();
+        assert_eq!(taxes, [1234, 300_000_000_000]);
+        Transfer::new(Cmd::from_str("+3000000000,checking,savings").unwrap()).unwrap().insert(&conn).unwrap();
+        let moved: i64 = conn.query_row("SELECT tax FROM transfer", [], |row| row.get(0)).unwrap();
+        assert_eq!(moved, 300_000_000_000);
+    }
+
+    #[test]
+    fn amounts_can_be_expressions() {
+        for (text, cents) in [("1450/3", 48333), ("1200+350", 155000), ("1200+350*2", 190000), ("100-20-30", 5000), ("-5+10", 500), ("0.05/2", 3), ("1k/4", 25000)] {
+            assert_eq!(parse_cents(text).unwrap(), Some(cents), "{}", text);
+        }
+        for text in ["100+", "100*/2", "5/0", "100+x"] {
+            assert!(matches!(parse_cents(text), Err(ParseCmdError { kind: ParseErrorKind::BadExpression, token: e, .. }) if e == text), "{}", text);
+        }
+        assert!(matches!(Cmd::from_str(">100+,food"), Err(LindaError::Parse(ParseCmdError { kind: ParseErrorKind::BadExpression, token: e, .. })) if e == "100+"));
+        let tr = Tr::new(Cmd::from_str(">1450/3,dinner").unwrap(), BASE_CURRENCY).unwrap();
+        assert_eq!((tr.tax, tr.category.as_str()), (48333, "dinner"));
+    }
+
+    #[test]
+    fn descriptions_are_free_text() {
+        let conn = memory_db();
+        for text in ["&1500,salary,march paycheck from acme", "&1500,salary", ">12,lunch,42"] {
+            Tr::new(Cmd::from_str(text).unwrap(), BASE_CURRENCY).unwrap().insert(&conn).unwrap();
+        }
+        let descriptions: Vec<Option<String>> = conn.prepare("SELECT description FROM `transaction` ORDER BY id").unwrap()
+            .query_map([], |row| row.get(0))
+            .unwrap()
+            .collect::<Result<_>>()
+            .unwrap();
+        assert_eq!(descriptions, [Some(String::from("march paycheck from acme")), None, Some(String::from("42"))]);
+    }
+
+    #[test]
+    fn durations_are_position_independent() {
+        let conn = memory_db();
+        for text in ["&200,consulting,~90", "&200,consulting,~90,acme", "&200,consulting,acme,~90", "&200,consulting,acme"] {
+            Tr::new(Cmd::from_str(text).unwrap(), BASE_CURRENCY).unwrap().insert(&conn).unwrap();
+        }
+        let rows: Vec<(i32, Option<String>)> = conn.prepare("SELECT duration, description FROM `transaction` ORDER BY id").unwrap()
+            .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))
+            .unwrap()
+            .collect::<Result<_>>()
+            .unwrap();
+        let acme = Some(String::from("acme"));
+        assert_eq!(rows, [(90, None), (90, acme.clone()), (90, acme.clone()), (0, acme)]);
+        assert!(matches!(Cmd::from_str("&200,consulting,~1h"), Err(LindaError::Parse(ParseCmdError { kind: ParseErrorKind::BadDuration, token: d, .. })) if d == "~1h"));
+        assert!(Tr::new(Cmd::from_str("&200,consulting,~90,~30").unwrap(), BASE_CURRENCY).is_err());
+    }
+
+    #[test]
+    fn descriptions_are_read_back() {
+        let conn = memory_db();
+        for text in ["&100,salary,monthly pay", "&100,salary"] {
+            insert(&conn, &Cmd::from_str(text).unwrap(), BASE_CURRENCY, false).unwrap();
+        }
+        let records = Record::select(&conn, Period::new(None, None), None, 10, false).unwrap();
+        let descriptions: Vec<(i64, Option<&str>)> = records.iter().map(|r| (r.id, r.tr.description.as_deref())).collect();
+        assert_eq!(descriptions, [(2, None), (1, Some("monthly pay"))]);
+    }
+
+    #[test]
+    fn tags_are_stored_once_and_lowercased() {
+        let mut conn = memory_db();
+        let cmd = Cmd::from_str(">600,restaurant,#Friends dinner #birthday #friends").unwrap();
+        assert_eq!(cmd.tags, ["friends", "birthday"]);
+        let tr = Tr::new(cmd, BASE_CURRENCY).unwrap();
+        assert_eq!(tr.description.as_deref(), Some("dinner"));
+        tr.insert(&conn).unwrap();
+        insert(&conn, &Cmd::from_str(">5,tea,#friends").unwrap(), BASE_CURRENCY, false).unwrap();
+        let records = Record::select(&conn, Period::new(None, None), None, 10, false).unwrap();
+        let mut first = records[1].tr.tags.clone();
+        first.sort();
+        assert_eq!(first, ["birthday", "friends"]);
+        assert_eq!(records[0].tr.tags, ["friends"]);
+        assert_eq!(records[0].tr.description, None);
+        let count = |conn: &Connection, table: &str| {
+            conn.query_row(&format!("SELECT count(*) FROM {}", table), [], |row| row.get::<_, i64>(0)).unwrap()
+        };
+        assert_eq!((count(&conn, "tag"), count(&conn, "transaction_tag")), (2, 3));
+        delete_row(&conn, 1).unwrap();
+        assert_eq!(count(&conn, "transaction_tag"), 3, "deleted rows keep their tags until purged");
+        purge_rows(&mut conn).unwrap();
+        assert_eq!(count(&conn, "transaction_tag"), 1);
+    }
+
+    #[test]
+    fn bare_minutes_follow_the_amount() {
+        let conn = memory_db();
+        for text in ["&200,90,consulting", "&200,consulting", "&200,45,consulting,client work"] {
+            insert(&conn, &Cmd::from_str(text).unwrap(), BASE_CURRENCY, false).unwrap();
+        }
+        let rows: Vec<(i64, i32, String, Option<String>)> = conn.prepare("SELECT tax, duration, (SELECT name FROM category WHERE id = category_id), description FROM `transaction` ORDER BY id").unwrap()
+            .query_map([], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?)))
+            .unwrap()
+            .collect::<Result<_>>()
+            .unwrap();
+        assert_eq!(rows, [
+            (20000, 90, String::from("consulting"), None),
+            (20000, 0, String::from("consulting"), None),
+            (20000, 45, String::from("consulting"), Some(String::from("client work"))),
+        ]);
+        assert!(matches!(Cmd::from_str("&200,90").unwrap().validate(), Err(InvalidCmdError::Mismatch(Field::Category, _))));
+        // Transfers have no duration, the number stays an account.
+        assert!(Transfer::new(Cmd::from_str("+5,1,savings").unwrap()).is_err());
+    }
+
+    #[test]
+    fn categories_are_normalized_unless_exact() {
+        let category = |text, syntax: &Syntax| Tr::new(Cmd::parse_with(text, syntax, Local::now()).unwrap(), BASE_CURRENCY).unwrap().category;
+        let normalized = Syntax::default();
+        for text in [">5,Food", ">5,  food ", r#">5," FOOD ""#, ">5,90,Food"] {
+            assert_eq!(category(text, &normalized), "food", "{}", text);
+        }
+        assert_eq!(category(">5,Take   Out", &normalized), "take out");
+        let exact = Syntax { normalize: false, ..Syntax::default() };
+        assert_eq!(category(">5,Take   Out", &exact), "Take   Out");
+        let tr = Tr::new(Cmd::from_str(">5,Food,Lunch With Bob").unwrap(), BASE_CURRENCY).unwrap();
+        assert_eq!(tr.description.as_deref(), Some("Lunch With Bob"));
+        let transfer = Transfer::new(Cmd::from_str("+5,Checking,Savings").unwrap()).unwrap();
+        assert_eq!((transfer.source.as_str(), transfer.target.as_str()), ("Checking", "Savings"));
+    }
+
+    #[test]
+    fn export_writes_aligned_csv() {
+        let db = temp_db("export");
+        let out = db.with_extension("csv");
+        init(&db, BASE_CURRENCY, false).unwrap();
+        run(&db, &parse_batch(r#"@2024-05-01 &1500,salary; @2024-05-02 >12.50,lunch,~30,"with ""Bob"", finally""#, &Syntax::default()).unwrap(), BASE_CURRENCY, false).unwrap();
+        export(&db, ExportFormat::Csv, Some(&out)).unwrap();
+        let mut reader = csv::Reader::from_path(&out).unwrap();
+        assert_eq!(reader.headers().unwrap(), CSV_HEADER.as_slice());
+        let rows: Vec<Vec<String>> = reader.records().map(|r| r.unwrap().iter().map(String::from).collect()).collect();
+        let noon = |d| Local.with_ymd_and_hms(2024, 5, d, 12, 0, 0).unwrap().to_rfc3339();
+        assert_eq!(rows, [
+            [String::from("1"), noon(1), String::from("1500.00"), String::from("salary"), String::from("0"), String::new(), String::from("income"), String::from("USD"), String::from("default")],
+            [String::from("2"), noon(2), String::from("12.50"), String::from("lunch"), String::from("30"), String::from(r#"with "Bob", finally"#), String::from("expense"), String::from("USD"), String::from("default")],
+        ]);
+        std::fs::remove_file(&db).unwrap();
+        std::fs::remove_file(&out).unwrap();
+    }
+
+    #[test]
+    fn import_is_all_or_nothing() {
+        let db = temp_db("import");
+        let csv = db.with_extension("csv");
+        init(&db, BASE_CURRENCY, false).unwrap();
+        run(&db, &parse_batch(">5,food", &Syntax::default()).unwrap(), BASE_CURRENCY, false).unwrap();
+        let header = CSV_HEADER.join(",");
+        let categories = || {
+            let conn = Connection::open(&db).unwrap();
+            let mut stmt = conn.prepare("SELECT (SELECT name FROM category WHERE id = category_id), tax FROM `transaction` ORDER BY id").unwrap();
+            stmt.query_map([], |row| Ok((row.get(0)?, row.get(1)?))).unwrap().collect::<Result<Vec<(String, i64)>>>().unwrap()
+        };
+
+        std::fs::write(&csv, format!("{}\n7,2024-05-01T12:00:00+00:00,12.50,  Food ,0,,expense,USD,default\n", header)).unwrap();
+        import(&db, &csv, &Syntax::default(), false).unwrap();
+        assert_eq!(categories(), [(String::from("food"), 500), (String::from("food"), 1250)]);
+
+        std::fs::write(&csv, format!("{}\n1,2024-05-01T12:00:00+00:00,3,tea,0,,expense,USD,default\n2,2024-05-01T12:00:00+00:00,x3,tea,0,,expense,USD,default\n", header)).unwrap();
+        let err = import(&db, &csv, &Syntax::default(), false).unwrap_err();
+        assert!(matches!(err, LindaError::Import(ImportError::Row(3, _))), "{}", err);
+        assert_eq!(categories().len(), 2);
+
+        std::fs::write(&csv, "").unwrap();
+        let err = import(&db, &csv, &Syntax::default(), false).unwrap_err();
+        assert!(matches!(&err, LindaError::Import(ImportError::Header(h)) if h.is_empty()), "{}", err);
+        assert_eq!(categories().len(), 2);
+        std::fs::remove_file(&db).unwrap();
+        std::fs::remove_file(&csv).unwrap();
+    }
+
+    #[test]
+    fn init_indexes_dates_and_categories() {
+        let conn = memory_db();
+        let plan: String = conn.query_row(
+            "EXPLAIN QUERY PLAN SELECT SUM(tax) FROM `transaction` WHERE category_id = 1", [], |row| row.get(3),
+        ).unwrap();
+        assert!(plan.contains("transaction_category"), "{}", plan);
+        let mut stmt = conn.prepare("SELECT name FROM sqlite_master WHERE type = 'index' AND name LIKE 'transaction_%' ORDER BY name").unwrap();
+        let names = stmt.query_map([], |row| row.get(0)).unwrap().collect::<Result<Vec<String>>>().unwrap();
+        assert_eq!(names, ["transaction_category", "transaction_created_at"]);
+    }
+
+    #[cfg(all(unix, not(target_os = "macos")))]
+    #[test]
+    fn data_db_follows_xdg() {
+        let base = env::temp_dir().join(format!("linda-xdg-{}", process::id()));
+        env::set_var("XDG_DATA_HOME", &base);
+        let db = data_db().unwrap();
+        env::remove_var("XDG_DATA_HOME");
+        assert_eq!(db, base.join("linda").join(DATABASE_FILENAME));
+        init(&db, BASE_CURRENCY, false).unwrap();
+        assert!(db.exists(), "init makes the directories it needs");
+        std::fs::remove_dir_all(&base).unwrap();
+    }
+
+    /// Times a month's totals and a category's monthly total on 100k rows,
+    /// with and without the indexes. `cargo test --release -- --ignored --nocapture`.
+    #[test]
+    #[ignore]
+    fn indexes_on_100k_rows() {
+        let mut conn = memory_db();
+        let tx = conn.transaction().unwrap();
+        for name in ["food", "rent", "travel", "fun"] {
+            tx.execute(Tr::INSERT_CATEGORY, [name]).unwrap();
+        }
+        let start = Local.with_ymd_and_hms(2020, 1, 1, 12, 0, 0).unwrap().timestamp();
+        let mut insert = tx.prepare(
+            "INSERT INTO `transaction` (created_at, kind, tax, category_id, duration, currency) VALUES (?1, 'expense', ?2, ?3, 0, 'USD')",
+        ).unwrap();
+        for i in 0..100_000i64 {
+            insert.execute(params![start + i * 1800, 100 + i % 5000, 1 + i % 4]).unwrap();
+        }
+        drop(insert);
+        tx.commit().unwrap();
+        let month = Period::new(NaiveDate::from_ymd_opt(2022, 3, 1), NaiveDate::from_ymd_opt(2022, 3, 31));
+        let time = |conn: &Connection| {
+            let at = std::time::Instant::now();
+            for _ in 0..100 {
+                totals(conn, month, None, BASE_CURRENCY).unwrap();
+                conn.query_row(
+                    "SELECT SUM(tax) FROM `transaction` WHERE category_id = 3 AND created_at >= ?1 AND created_at < ?2",
+                    [month.since, month.until],
+                    |row| row.get::<_, Option<i64>>(0),
+                ).unwrap();
+            }
+            at.elapsed() / 100
+        };
+        let indexed = time(&conn);
+        conn.execute_batch("DROP INDEX transaction_created_at; DROP INDEX transaction_category;").unwrap();
+        let scanned = time(&conn);
+        println!("totals of a month of 100k rows: {:?} with indexes, {:?} without", indexed, scanned);
+    }
+
+    /// The table `init` made before there were migrations, with a row in it.
+    fn baseline(db: &Path) {
+        let conn = Connection::open(db).unwrap();
+        conn.execute_batch(
+            "CREATE TABLE `transaction` (
+              id INTEGER PRIMARY KEY AUTOINCREMENT,
+              created_at INTEGER NOT NULL,
+              tax INTEGER NOT NULL,
+              category TEXT NOT NULL,
+              duration INTEGER DEFAULT 0,
+              description TEXT
+            );
+            INSERT INTO `transaction` (created_at, tax, category, duration) VALUES (1700000000, 5000, 'salary', 0);"
+        ).unwrap();
+    }
+
+    fn columns(conn: &Connection) -> Vec<(String, String)> {
+        conn.prepare(
+            "SELECT m.name, p.name FROM sqlite_master m, pragma_table_info(m.name) p
+             WHERE m.type = 'table' AND m.name NOT LIKE 'sqlite_%' ORDER BY m.name, p.name",
+        ).unwrap()
+            .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))
+            .unwrap()
+            .collect::<Result<_>>()
+            .unwrap()
+    }
+
+    #[test]
+    fn init_migrates_a_baseline_database() {
+        let db = temp_db("baseline");
+        baseline(&db);
+        assert_eq!(init(&db, "eur", false).unwrap(), Some(0));
+        assert_eq!(columns(&Connection::open(&db).unwrap()), columns(&memory_db()));
+        let old = &list(&db, Period::new(None, None), None, 10, false).unwrap()[0].tr;
+        assert_eq!((old.kind, old.tax, old.category.as_str(), old.currency.as_str(), old.account.as_str()), (OrderKind::Income, 5000, "salary", "EUR", DEFAULT_ACCOUNT));
+        run(&db, &parse_batch(">2.50,coffee", &Syntax::default()).unwrap(), BASE_CURRENCY, false).unwrap();
+        assert_eq!(list(&db, Period::new(None, None), None, 10, false).unwrap().len(), 2);
+        assert_eq!(init(&db, "eur", false).unwrap(), Some(SCHEMA_VERSION), "nothing left to migrate");
+        let kept = Connection::open(backup_path(&db, 0)).unwrap();
+        assert_eq!(columns(&kept).len(), 6, "the backup is the table as it was");
+        std::fs::remove_file(backup_path(&db, 0)).unwrap();
+        std::fs::remove_file(&db).unwrap();
+    }
+
+    /// Brings `conn` to schema `version` the way it got there in the past,
+    /// one migration at a time.
+    fn at_version(conn: &mut Connection, version: usize) {
+        conn.execute("CREATE TABLE schema_version (version INTEGER PRIMARY KEY, applied_at INTEGER NOT NULL)", ()).unwrap();
+        for (i, migration) in MIGRATIONS[..version].iter().enumerate() {
+            migration(conn, BASE_CURRENCY).unwrap();
+            conn.execute("INSERT INTO schema_version (version, applied_at) VALUES (?1, 0)", [i + 1]).unwrap();
+        }
+    }
+
+    fn indexes(conn: &Connection) -> Vec<String> {
+        conn.prepare("SELECT name FROM sqlite_master WHERE type = 'index' AND name NOT LIKE 'sqlite_%' ORDER BY name")
+            .unwrap()
+            .query_map([], |row| row.get(0))
+            .unwrap()
+            .collect::<Result<_>>()
+            .unwrap()
+    }
+
+    #[test]
+    fn every_version_migrates_to_the_latest_schema() {
+        let latest = memory_db();
+        for version in 0..=SCHEMA_VERSION {
+            let mut conn = Connection::open_in_memory().unwrap();
+            at_version(&mut conn, version);
+            assert_eq!(migrate(&mut conn, BASE_CURRENCY).unwrap(), version);
+            assert_eq!(schema_version(&conn).unwrap(), SCHEMA_VERSION);
+            assert_eq!(columns(&conn), columns(&latest), "from version {}", version);
+            assert_eq!(indexes(&conn), indexes(&latest), "from version {}", version);
+        }
+    }
+
+    #[test]
+    fn init_force_starts_over() {
+        let db = temp_db("force");
+        assert_eq!(init(&db, BASE_CURRENCY, true).unwrap(), None, "nothing to delete yet");
+        run(&db, &parse_batch(">5,tea;>6,tea", &Syntax::default()).unwrap(), BASE_CURRENCY, false).unwrap();
+        assert_eq!(init(&db, BASE_CURRENCY, false).unwrap(), Some(SCHEMA_VERSION));
+        assert_eq!(count_transactions(&db).unwrap(), 2, "init alone keeps the rows");
+        assert_eq!(init(&db, BASE_CURRENCY, true).unwrap(), None);
+        assert_eq!(count_transactions(&db).unwrap(), 0);
+        std::fs::write(&db, "not a database").unwrap();
+        assert!(count_transactions(&db).is_err());
+        assert_eq!(init(&db, BASE_CURRENCY, true).unwrap(), None, "even a file linda can't read goes");
+        std::fs::remove_file(&db).unwrap();
+    }
+
+    #[test]
+    fn categories_are_backfilled_and_reused() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        // Just before create_categories.
+        at_version(&mut conn, 8);
+        conn.execute_batch(
+            "INSERT INTO `transaction` (created_at, tax, category) VALUES (1700000000, 100, 'tea'), (1700000001, 200, 'food'), (1700000002, 300, 'tea');"
+        ).unwrap();
+        migrate(&mut conn, BASE_CURRENCY).unwrap();
+        let names = |conn: &Connection| conn.prepare("SELECT id, name FROM category ORDER BY id").unwrap()
+            .query_map([], |row| Ok((row.get(0)?, row.get(1)?))).unwrap()
+            .collect::<Result<Vec<(i64, String)>>>().unwrap();
+        assert_eq!(names(&conn), [(1, String::from("food")), (2, String::from("tea"))]);
+        let ids = |conn: &Connection| conn.prepare("SELECT category_id FROM `transaction` ORDER BY id").unwrap()
+            .query_map([], |row| row.get(0)).unwrap()
+            .collect::<Result<Vec<i64>>>().unwrap();
+        assert_eq!(ids(&conn), [2, 1, 2]);
+        insert(&conn, &Cmd::from_str(">5,tea").unwrap(), BASE_CURRENCY, false).unwrap();
+        insert(&conn, &Cmd::from_str(">5,rent").unwrap(), BASE_CURRENCY, false).unwrap();
+        let ids = ids(&conn);
+        assert_eq!(ids[..4], [2, 1, 2, 2], "tea keeps its id");
+        assert!(!ids[..4].contains(&ids[4]), "rent gets one of its own");
+        assert_eq!(names(&conn).len(), 3);
+    }
+
+    #[test]
+    fn accounts_are_backfilled() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        // Just before create_accounts.
+        at_version(&mut conn, 11);
+        conn.execute_batch(
+            "INSERT INTO category (name) VALUES ('tea');
+             INSERT INTO `transaction` (created_at, tax, category_id, account) VALUES (1700000000, 100, 1, 'default'), (1700000001, 200, 1, 'cash');
+             INSERT INTO transfer (created_at, tax, source, target) VALUES (1700000002, 50, 'checking', 'cash');"
+        ).unwrap();
+        migrate(&mut conn, BASE_CURRENCY).unwrap();
+        let names = conn.prepare("SELECT name FROM account ORDER BY id").unwrap()
+            .query_map([], |row| row.get(0)).unwrap()
+            .collect::<Result<Vec<String>>>().unwrap();
+        assert_eq!(names, [DEFAULT_ACCOUNT, "cash", "checking"]);
+        let accounts: Vec<String> = Record::all(&conn).unwrap().into_iter().map(|r| r.tr.account).collect();
+        assert_eq!(accounts, [DEFAULT_ACCOUNT, "cash"]);
+    }
+
+    #[test]
+    fn open_migrates_after_a_backup() {
+        let db = temp_db("open-migrates");
+        let mut conn = Connection::open(&db).unwrap();
+        at_version(&mut conn, CURRENCY_VERSION + 1);
+        conn.execute("INSERT INTO `transaction` (created_at, tax, category) VALUES (1700000000, 250, 'tea')", ()).unwrap();
+        drop(conn);
+        let records = list(&db, Period::new(None, None), None, 10, false).unwrap();
+        assert_eq!((records[0].tr.tax, records[0].tr.account.as_str()), (250, DEFAULT_ACCOUNT));
+        assert_eq!(schema_version(&Connection::open(&db).unwrap()).unwrap(), SCHEMA_VERSION);
+        let backup = backup_path(&db, CURRENCY_VERSION + 1);
+        assert_eq!(schema_version(&Connection::open(&backup).unwrap()).unwrap(), CURRENCY_VERSION + 1);
+        std::fs::remove_file(&backup).unwrap();
+        std::fs::remove_file(&db).unwrap();
+
+        at_version(&mut Connection::open(&db).unwrap(), CURRENCY_VERSION - 1);
+        let err = list(&db, Period::new(None, None), None, 10, false).unwrap_err();
+        assert!(matches!(err, LindaError::OutdatedSchema(v) if v == CURRENCY_VERSION - 1), "{:?}", err);
+        assert!(!backup_path(&db, CURRENCY_VERSION - 1).exists());
+        std::fs::remove_file(&db).unwrap();
+
+        init(&db, BASE_CURRENCY, false).unwrap();
+        Connection::open(&db).unwrap().execute("INSERT INTO schema_version (version, applied_at) VALUES (?1, 0)", [SCHEMA_VERSION + 1]).unwrap();
+        assert!(matches!(list(&db, Period::new(None, None), None, 10, false), Err(LindaError::NewerSchema(v)) if v == SCHEMA_VERSION + 1));
+        assert!(matches!(init(&db, BASE_CURRENCY, false), Err(LindaError::NewerSchema(_))));
+        std::fs::remove_file(&db).unwrap();
+
+        std::fs::write(&db, "").unwrap();
+        assert!(matches!(list(&db, Period::new(None, None), None, 10, false), Err(LindaError::NotInitialized(_))));
+        assert_eq!(init(&db, BASE_CURRENCY, false).unwrap(), None, "an empty file gets the latest schema at once");
+        assert_eq!(schema_version(&Connection::open(&db).unwrap()).unwrap(), SCHEMA_VERSION);
+        std::fs::remove_file(&db).unwrap();
+    }
+
+    #[test]
+    fn init_converts_text_timestamps() {
+        let db = temp_db("text-timestamps");
+        baseline(&db);
+        Connection::open(&db).unwrap().execute_batch(
+            "INSERT INTO `transaction` (created_at, tax, category) VALUES ('2024-03-01 12:00', 100, 'a'), ('2024-03-01T12:00:00+02:00', 200, 'b');"
+        ).unwrap();
+        init(&db, BASE_CURRENCY, false).unwrap();
+        let mut seconds: Vec<i64> = list(&db, Period::new(None, None), None, 10, false).unwrap().iter().map(|r| r.tr.created_at.timestamp()).collect();
+        seconds.sort();
+        assert_eq!(seconds, [1700000000, 1709287200, 1709294400]);
+
+        Connection::open(&db).unwrap().execute("INSERT INTO `transaction` (created_at, tax, category_id) VALUES ('soon', 1, 1)", ()).unwrap();
+        let err = list(&db, Period::new(None, None), None, 10, false).unwrap_err();
+        assert!(matches!(&err, LindaError::BadTimestamp(4, text) if text == "soon"), "{}", err);
+        assert!(matches!(report(&db, Period::new(None, None), None, BASE_CURRENCY), Err(LindaError::BadTimestamp(4, _))));
+        std::fs::remove_file(backup_path(&db, 0)).unwrap();
+        std::fs::remove_file(&db).unwrap();
+    }
+
+    /// Clocks change at these instants in the US and in Europe. Run with
+    /// `TZ=Europe/Berlin` or `TZ=America/New_York` to cross them locally.
+    const CLOCK_CHANGES: [i64; 4] = [1710054000, 1711846800, 1729990800, 1730613600];
+
+    #[test]
+    fn timestamps_round_trip_across_clock_changes() {
+        let db = temp_db("clock-changes");
+        init(&db, BASE_CURRENCY, false).unwrap();
+        let secs: Vec<i64> = CLOCK_CHANGES.iter().flat_map(|&at| [at - 1800, at, at + 1800]).collect();
+        let cmds: Vec<Cmd> = secs.iter()
+            .map(|&s| Cmd { created_at: from_timestamp(s).unwrap(), ..Cmd::from_str(">1,x").unwrap() })
+            .collect();
+        run(&db, &cmds, BASE_CURRENCY, false).unwrap();
+        let stored: Vec<i64> = list(&db, Period::new(None, None), None, 100, false).unwrap().iter().rev().map(|r| r.tr.created_at.timestamp()).collect();
+        assert_eq!(stored, secs);
+        std::fs::remove_file(&db).unwrap();
+    }
+
+    #[test]
+    fn dates_on_clock_changes_are_noon() {
+        for date in ["2024-03-10", "2024-03-31", "2024-10-27", "2024-11-03"] {
+            let created_at = Cmd::from_str(&format!("@{} >1,x", date)).unwrap().created_at;
+            assert_eq!(created_at.format("%Y-%m-%d %H:%M").to_string(), format!("{} 12:00", date));
+            assert_eq!(from_timestamp(created_at.timestamp()), Some(created_at));
+        }
+    }
+
+    /// An in-memory database with the latest schema.
+    fn memory_db() -> Connection {
+        open_in_memory(BASE_CURRENCY).unwrap()
+    }
+
+    /// A database file of its own for the test.
+    fn temp_db(name: &str) -> PathBuf {
+        let db = env::temp_dir().join(format!("linda-{}-{}.db", name, process::id()));
+        let _ = std::fs::remove_file(&db);
+        db
+    }
+
+    #[test]
+    fn display_round_trips() {
+        let round_trip = |cmd: &Cmd| {
+            let again = Cmd::from_str(&cmd.to_string()).unwrap();
+            assert_eq!(format!("{:?}", again.pack), format!("{:?}", cmd.pack), "{}", cmd);
+            assert_eq!(again.tags, cmd.tags, "{}", cmd);
+        };
+        for text in ["&100,salary", ">12.50,lunch,~30,with bob", "&200,90,consulting", "+5,checking,savings", ">5,tea,#Morning #daily"] {
+            round_trip(&Cmd::from_str(text).unwrap());
+        }
+        assert_eq!(Cmd::from_str(">12.50,Lunch , with bob").unwrap().to_string(), ">12.50,lunch,with bob");
+        for word in ["", " padded ", "a,b", "a;b", r#"say "hi""#, "~30", "@home", "-5", "12", ".5", "#tag", "a #tag"] {
+            let pack = vec![
+                PartOfCmdKind::Mod('>'), PartOfCmdKind::Digit(500), PartOfCmdKind::Word(String::from("tea")), PartOfCmdKind::Word(String::from(word)),
+            ];
+            let cmd = Cmd { op: Cmd::read_op(&pack, &None).unwrap(), pack, created_at: Local::now(), tags: vec![], currency: None, account: None };
+            round_trip(&cmd);
+        }
+    }
+
+    #[test]
+    fn timestamps_round_trip() {
+        let conn = memory_db();
+        let tr = Tr::new(Cmd::from_str(">3,tea").unwrap(), BASE_CURRENCY).unwrap();
+        tr.insert(&conn).unwrap();
+        let (kind, secs): (String, i64) = conn.query_row(
+            "SELECT typeof(created_at), created_at FROM `transaction`", [], |row| Ok((row.get(0)?, row.get(1)?)),
+        ).unwrap();
+        assert_eq!(kind, "integer");
+        assert_eq!(from_timestamp(secs).unwrap(), tr.created_at.with_nanosecond(0).unwrap());
+    }
+
+    #[test]
+    fn schema_file_accepts_inserts() {
+        let conn = Connection::open_in_memory().unwrap();
+        conn.execute_batch(include_str!("../sql/scheme.sql")).unwrap();
+        Tr::new(Cmd::from_str(">450,groceries").unwrap(), BASE_CURRENCY).unwrap().insert(&conn).unwrap();
+        Transfer::new(Cmd::from_str("+50,checking,savings").unwrap()).unwrap().insert(&conn).unwrap();
+        let count: i64 = conn.query_row("SELECT count(*) FROM `transaction`", [], |row| row.get(0)).unwrap();
+        assert_eq!(count, 1);
+    }
+
+    #[test]
+    fn date_prefix_backdates() {
+        let conn = memory_db();
+        let tr = Tr::new(Cmd::from_str("@2024-05-03 >450,groceries").unwrap(), BASE_CURRENCY).unwrap();
+        tr.insert(&conn).unwrap();
+        let secs: i64 = conn.query_row("SELECT created_at FROM `transaction`", [], |row| row.get(0)).unwrap();
+        let expected = Local.with_ymd_and_hms(2024, 5, 3, 12, 0, 0).unwrap();
+        assert_eq!(from_timestamp(secs).unwrap(), expected);
+        assert_eq!((tr.tax, tr.category.as_str()), (45000, "groceries"));
+        assert!(matches!(Cmd::from_str("@2024-02-31 >450,groceries"), Err(LindaError::Parse(ParseCmdError { kind: ParseErrorKind::BadDate, token: d, .. })) if d == "2024-02-31"));
+        assert!(matches!(Cmd::from_str("@2024-05-03"), Err(LindaError::Parse(ParseCmdError { kind: ParseErrorKind::Empty, .. }))));
+    }
+
+    #[test]
+    fn accounts_are_named_and_checked() {
+        let parse = |text| Cmd::from_str(text).map(|cmd| cmd.account);
+        assert_eq!(parse(">12,lunch,@card").unwrap().as_deref(), Some("card"));
+        assert_eq!(parse("expense 12 @my-card lunch").unwrap().as_deref(), Some("my-card"));
+        assert_eq!(parse(">12,lunch,@2024-05-01").unwrap(), None, "a date isn't an account");
+        let kind = |text| match Cmd::from_str(text) {
+            Err(LindaError::Parse(err)) => err.kind,
+            other => panic!("{:?}", other),
+        };
+        assert_eq!(kind(">12,lunch,@card,@cash"), ParseErrorKind::TwoAccounts);
+        assert_eq!(kind("+5,checking,savings,@cash"), ParseErrorKind::TransferAccount);
+        assert_eq!(kind(">12,lunch,@2024-13-01"), ParseErrorKind::BadDate);
+
+        let db = temp_db("accounts-table");
+        init(&db, BASE_CURRENCY, false).unwrap();
+        let exec = |text, new_accounts| run(&db, &parse_batch(text, &Syntax::default()).unwrap(), BASE_CURRENCY, new_accounts);
+        assert!(matches!(exec(">12,lunch,@card", false), Err(LindaError::UnknownAccount(name)) if name == "card"));
+        assert!(matches!(exec("+5,checking,savings", false), Err(LindaError::UnknownAccount(name)) if name == "checking"));
+        exec("&100,salary", false).unwrap();
+        exec(">12,lunch,@card", true).unwrap();
+        exec(">3,tea,@card;+20,default,card", false).unwrap();
+        assert!(new_account(&db, "cash").unwrap());
+        assert!(!new_account(&db, "cash").unwrap(), "already there");
+        let totals: Vec<_> = accounts(&db).unwrap().into_iter().map(|a| (a.account, a.count, a.balance)).collect();
+        assert_eq!(totals, [
+            (String::from("card"), 2, 500),
+            (String::from("cash"), 0, 0),
+            (String::from(DEFAULT_ACCOUNT), 1, 8000),
+        ]);
+        assert_eq!(balance(&db, Some("card")).unwrap().balance, 500);
+        std::fs::remove_file(&db).unwrap();
+    }
+
+    #[test]
+    fn import_checks_accounts() {
+        let (from, to) = (temp_db("import-accounts-from"), temp_db("import-accounts-to"));
+        let csv = from.with_extension("csv");
+        init(&from, BASE_CURRENCY, false).unwrap();
+        run(&from, &parse_batch(">12,lunch,@card", &Syntax::default()).unwrap(), BASE_CURRENCY, true).unwrap();
+        export(&from, ExportFormat::Csv, Some(&csv)).unwrap();
+        init(&to, BASE_CURRENCY, false).unwrap();
+        assert!(matches!(import(&to, &csv, &Syntax::default(), false), Err(LindaError::UnknownAccount(name)) if name == "card"));
+        assert!(Record::all(&open(&to).unwrap()).unwrap().is_empty());
+        import(&to, &csv, &Syntax::default(), true).unwrap();
+        assert_eq!(Record::all(&open(&to).unwrap()).unwrap()[0].tr.account, "card");
+        for file in [from, to, csv] {
+            std::fs::remove_file(file).unwrap();
+        }
+    }
+
+    #[test]
+    fn shorthands_keep_account_and_date() {
+        let db = temp_db("shorthand-fields");
+        init(&db, BASE_CURRENCY, false).unwrap();
+        assert_eq!(shorthand(&db, "lunch", ">12,Lunch,@card", &Syntax::default()).unwrap(), ">12,lunch,@card");
+        let pipes = Syntax::with_sep('|').unwrap();
+        assert_eq!(shorthand(&db, "rent", "&900|rent|@yesterday|\"a, b\"", &pipes).unwrap(), r#"&900,rent,"a, b",@yesterday"#);
+        let syntax = Syntax { shorthands: shorthands(&db).unwrap(), ..Syntax::default() };
+        let now = Local.with_ymd_and_hms(2024, 5, 15, 9, 30, 0).unwrap();
+        let lunch = Cmd::parse_with("lunch 15", &syntax, now).unwrap();
+        assert_eq!((lunch.to_string().as_str(), lunch.account.as_deref()), (">15,lunch", Some("card")));
+        let rent = Cmd::parse_with("rent", &syntax, now).unwrap();
+        assert_eq!((rent.created_at.date_naive(), rent.account.as_deref()), (NaiveDate::from_ymd_opt(2024, 5, 14).unwrap(), None));
+        assert_eq!(rent.to_string(), r#"&900,rent,"a, b""#);
+        std::fs::remove_file(&db).unwrap();
+    }
+
+    #[test]
+    fn duplicates_match_day_window_and_account() {
+        let mut conn = memory_db();
+        store(&mut conn, &parse_batch("@2024-05-03 >12,lunch,@card", &Syntax::default()).unwrap(), BASE_CURRENCY, true).unwrap();
+        let found = |text, hours| find_duplicates(&conn, &parse_batch(text, &Syntax::default()).unwrap(), BASE_CURRENCY, hours)
+            .unwrap().into_iter().map(|d| (d.text, d.id)).collect::<Vec<_>>();
+        assert_eq!(found("@2024-05-03 >12,lunch,@card", None), [(String::from(">12USD,lunch,@2024-05-03,@card"), 1)]);
+        assert!(found("@2024-05-03 >12,lunch,@cash", None).is_empty(), "another account");
+        assert!(found("@2024-05-03 >12,dinner,@card;@2024-05-03 &12,lunch,@card;@2024-05-03 >13,lunch,@card", None).is_empty());
+        assert!(found("@2024-05-04 >12,lunch,@card", None).is_empty(), "another day");
+        assert_eq!(found("@2024-05-04 >12,lunch,@card", Some(24)).len(), 1, "within a day of noon");
+        assert!(found("@2024-05-04 >12,lunch,@card", Some(23)).is_empty());
+        delete_row(&conn, 1).unwrap();
+        assert!(found("@2024-05-03 >12,lunch,@card", None).is_empty(), "deleted rows don't count");
+    }
+
+    #[test]
+    fn date_fields_backdate() {
+        let now = Local.with_ymd_and_hms(2024, 5, 15, 9, 30, 0).unwrap();
+        let parse = |text| Cmd::parse_with(text, &Syntax::default(), now);
+        let day = |text| parse(text).unwrap().created_at.date_naive();
+        let jan5 = NaiveDate::from_ymd_opt(2024, 1, 5).unwrap();
+        assert_eq!(day("&100,10,salary,@2024-01-05"), jan5);
+        assert_eq!(day("&100,@2024-01-05,10,salary"), jan5, "anywhere after the amount");
+        assert_eq!(day("income 100 @2024-01-05 salary for may"), jan5);
+        assert_eq!(day(">3,lunch,@yesterday"), NaiveDate::from_ymd_opt(2024, 5, 14).unwrap());
+        let tr = Tr::new(parse("&100,10,salary,@2024-01-05").unwrap(), BASE_CURRENCY).unwrap();
+        assert_eq!((tr.tax, tr.duration, tr.category.as_str(), tr.description.as_deref()), (10000, 10, "salary", None));
+
+        match parse(">3,lunch,@2024-13-01") {
+            Err(LindaError::Parse(err)) => {
+                assert_eq!((err.kind, err.token.as_str(), err.pos), (ParseErrorKind::BadDate, "2024-13-01", 10));
+            },
+            other => panic!("{:?}", other),
+        }
+        assert!(matches!(parse("@2024-01-05 >3,lunch,@2024-01-06"), Err(LindaError::Parse(ParseCmdError { kind: ParseErrorKind::TwoDates, .. }))));
+        assert!(matches!(parse(">3,lunch,@today,@today"), Err(LindaError::Parse(ParseCmdError { kind: ParseErrorKind::TwoDates, .. }))));
+        let quoted = Tr::new(parse(r#">3,lunch,"@home""#).unwrap(), BASE_CURRENCY).unwrap();
+        assert_eq!((quoted.description.as_deref(), quoted.created_at), (Some("@home"), now));
+        let again = Tr::new(parse(&tr.to_cmd()).unwrap(), BASE_CURRENCY).unwrap();
+        assert_eq!((again.created_at.date_naive(), again.tax, again.category), (jan5, 10000, tr.category));
+    }
+
+    #[test]
+    fn relative_dates_use_the_given_now() {
+        // A Wednesday.
+        let now = Local.with_ymd_and_hms(2024, 5, 15, 9, 30, 0).unwrap();
+        let day = |text| Cmd::parse_with(text, &Syntax::default(), now).unwrap().created_at.date_naive();
+        let ymd = |y, m, d| NaiveDate::from_ymd_opt(y, m, d).unwrap();
+        assert_eq!(Cmd::parse_with(">3,lunch", &Syntax::default(), now).unwrap().created_at, now);
+        assert_eq!(day("@today >3,lunch"), ymd(2024, 5, 15));
+        assert_eq!(day("@yesterday >3,lunch"), ymd(2024, 5, 14));
+        assert_eq!(day("@-3d &50,refund"), ymd(2024, 5, 12));
+        assert_eq!(day("@-2w >3,lunch"), ymd(2024, 5, 1));
+        assert_eq!(day("@mon >3,lunch"), ymd(2024, 5, 13));
+        assert_eq!(day("@Thursday >3,lunch"), ymd(2024, 5, 9));
+        assert_eq!(day("@wed >3,lunch"), ymd(2024, 5, 15));
+        assert!(matches!(Cmd::parse_with("@lastweek >3,lunch", &Syntax::default(), now), Err(LindaError::Parse(ParseCmdError { kind: ParseErrorKind::BadDate, token: d, .. })) if d == "lastweek"));
+        assert!(matches!(Cmd::parse_with("@-xd >3,lunch", &Syntax::default(), now), Err(LindaError::Parse(ParseCmdError { kind: ParseErrorKind::BadDate, .. }))));
+    }
+
+    #[test]
+    fn unsupported_shapes_are_errors() {
+        let conn = memory_db();
+        let err = insert(&conn, &Cmd::from_str("&12,5").unwrap(), BASE_CURRENCY, false).unwrap_err();
+        assert_eq!(err.to_string(), "Unrecognized command shape: modifier '&', amount 12.00, amount 5.00");
+    }
+
+    #[test]
+    fn list_is_newest_first() {
+        let conn = memory_db();
+        for text in ["@2024-05-01 >3,tea", "@2024-05-03 >4,lunch", "@2024-05-02 &50,refund"] {
+            Tr::new(Cmd::from_str(text).unwrap(), BASE_CURRENCY).unwrap().insert(&conn).unwrap();
+        }
+        let records = Record::select(&conn, Period::new(None, None), None, 2, false).unwrap();
+        let seen: Vec<(i64, &str)> = records.iter().map(|r| (r.id, r.tr.category.as_str())).collect();
+        assert_eq!(seen, [(2, "lunch"), (3, "refund")]);
+        assert!(matches!(records[1].tr.kind, OrderKind::Income));
+    }
+
+    #[test]
+    fn quoted_fields_keep_the_separator() {
+        let conn = memory_db();
+        for text in [r#">350,"food, delivery""#, r#">5,"say ""hi""",  " padded ""#, r#">5,"a \"b\"""#, r#">5,"12""#] {
+            Tr::new(Cmd::from_str(text).unwrap(), BASE_CURRENCY).unwrap().insert(&conn).unwrap();
+        }
+        let rows: Vec<(String, Option<String>)> = conn.prepare("SELECT (SELECT name FROM category WHERE id = category_id), description FROM `transaction` ORDER BY id").unwrap()
+            .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))
+            .unwrap()
+            .collect::<Result<_>>()
+            .unwrap();
+        assert_eq!(rows, [
+            (String::from("food, delivery"), None),
+            (String::from(r#"say "hi""#), Some(String::from(" padded "))),
+            (String::from(r#"a "b""#), None),
+            (String::from("12"), None),
+        ]);
+        assert!(matches!(Cmd::from_str(r#">350,"food"#), Err(LindaError::Parse(ParseCmdError { kind: ParseErrorKind::UnterminatedQuote, pos: 5, .. }))));
+        assert!(matches!(Cmd::from_str(r#">350,"food"x"#), Err(LindaError::Parse(ParseCmdError { kind: ParseErrorKind::AfterQuote, pos: 11, .. }))));
+    }
+
+    #[test]
+    fn batches_split_on_semicolons() {
+        let cmds = parse_batch(">120,coffee; >340,groceries; &5000,salary;", &Syntax::default()).unwrap();
+        let categories: Vec<String> = cmds.into_iter().map(|c| Tr::new(c, BASE_CURRENCY).unwrap().category).collect();
+        assert_eq!(categories, ["coffee", "groceries", "salary"]);
+        assert_eq!(parse_batch(r#">5,"a;b""#, &Syntax::default()).unwrap().len(), 1);
+        let err = parse_batch(">120,coffee; >abc; &5000,salary", &Syntax::default()).unwrap_err();
+        assert_eq!((err.index, err.text.as_str()), (2, ">abc"));
+        assert_eq!(err.to_string(), "Command 2 '>abc' failed: unexpected token 'abc' at position 1: expected an amount like 12, 12.50 or 1.2k");
+    }
+
+    #[test]
+    fn failed_batches_store_nothing() {
+        let mut conn = memory_db();
+        let tx = conn.transaction().unwrap();
+        insert(&tx, &Cmd::from_str(">120,coffee").unwrap(), BASE_CURRENCY, false).unwrap();
+        assert!(insert(&tx, &Cmd::from_str("&12,5").unwrap(), BASE_CURRENCY, false).is_err());
+        drop(tx);
+        let count: i64 = conn.query_row("SELECT count(*) FROM `transaction`", [], |row| row.get(0)).unwrap();
+        assert_eq!(count, 0);
+    }
+
+    #[test]
+    fn backup_copies_without_overwriting() {
+        let db = temp_db("backup");
+        let to = db.with_extension("copy.db");
+        let _ = std::fs::remove_file(&to);
+        assert!(matches!(backup(&db, Some(&to)), Err(LindaError::NotInitialized(_))));
+        assert!(!db.exists() && !to.exists(), "no empty database or backup is left behind");
+
+        init(&db, BASE_CURRENCY, false).unwrap();
+        run(&db, &parse_batch(">5,tea;>6,tea", &Syntax::default()).unwrap(), BASE_CURRENCY, false).unwrap();
+        assert_eq!(backup(&db, Some(&to)).unwrap(), (to.clone(), 2));
+        assert_eq!(list(&to, Period::new(None, None), None, 10, false).unwrap().len(), 2);
+        assert!(matches!(backup(&db, Some(&to)), Err(LindaError::Io(_))), "{} is kept", to.display());
+
+        let (named, count) = backup(&db, None).unwrap();
+        assert_eq!(count, 2);
+        assert_eq!(named.parent(), db.parent());
+        let name = named.file_name().unwrap().to_str().unwrap();
+        assert!(name.starts_with(&format!("{}-", db.file_stem().unwrap().to_str().unwrap())) && name.ends_with(".db"), "{}", name);
+        for path in [&db, &to, &named] {
+            std::fs::remove_file(path).unwrap();
+        }
+    }
+
+    /// What is left, and how many transfers, after running each of `lines`
+    /// and undoing once.
+    fn undo_after(lines: &[&str]) -> (Vec<String>, i64, Undone) {
+        let mut conn = memory_db();
+        for line in lines {
+            store(&mut conn, &parse_batch(line, &Syntax::default()).unwrap(), BASE_CURRENCY, true).unwrap();
+        }
+        let undone = undo_batch(&mut conn).unwrap();
+        let left = Record::all(&conn).unwrap().into_iter().map(|record| record.tr.category).collect();
+        let transfers = conn.query_row("SELECT COUNT(*) FROM transfer", [], |row| row.get(0)).unwrap();
+        (left, transfers, undone)
+    }
+
+    #[test]
+    fn undo_takes_back_the_newer_row() {
+        let (left, _, undone) = undo_after(&[">2.50,coffee", "@2024-05-03 >3,tea,green"]);
+        assert_eq!(left, ["coffee"]);
+        assert_eq!(undone.lines, ["expense #2: 3.00 USD tea, green (2024-05-03)"]);
+        assert_eq!((undone.text.as_str(), undone.account), (">3USD,tea,green,@2024-05-03", None));
+        let mut conn = memory_db();
+        assert!(matches!(undo_batch(&mut conn), Err(LindaError::NothingToUndo)));
+        assert_eq!(LindaError::NothingToUndo.to_string(), "There is no transaction to undo");
+    }
+
+    #[test]
+    fn undo_takes_back_the_whole_command() {
+        let (left, transfers, undone) = undo_after(&[">2.50,coffee", "+5,main,savings,@2024-05-03"]);
+        assert_eq!((left, transfers), (vec![String::from("coffee")], 0));
+        assert_eq!(undone.text, "+5,main,savings,@2024-05-03");
+        let (left, _, undone) = undo_after(&[">2.50,coffee", "&100%10,gig"]);
+        assert_eq!(left, ["coffee"]);
+        assert_eq!(undone.lines.len(), 2, "both rows of the tax share");
+        let (left, transfers, _) = undo_after(&[">2.50,coffee", ">3,tea;>4,cake;+1,main,savings"]);
+        assert_eq!((left, transfers), (vec![String::from("coffee"), String::from("tea"), String::from("cake")], 0));
+        let mut conn = memory_db();
+        store(&mut conn, &parse_batch("@2024-05-03 >3,tea,@work", &Syntax::default()).unwrap(), BASE_CURRENCY, true).unwrap();
+        let undone = undo_batch(&mut conn).unwrap();
+        assert_eq!((undone.text.as_str(), undone.account), (">3USD,tea,@2024-05-03,@work", None));
+        let mut cmds = parse_batch(">3,tea", &Syntax::default()).unwrap();
+        cmds[0].account = Some(String::from("day job"));
+        store(&mut conn, &cmds, BASE_CURRENCY, true).unwrap();
+        assert_eq!(undo_batch(&mut conn).unwrap().account.as_deref(), Some("day job"), "not a name @ can give");
+    }
+
+    #[test]
+    fn undo_takes_back_a_whole_import() {
+        let db = temp_db("undo-import");
+        let csv = db.with_extension("csv");
+        init(&db, BASE_CURRENCY, false).unwrap();
+        run(&db, &parse_batch(">2.50,coffee", &Syntax::default()).unwrap(), BASE_CURRENCY, false).unwrap();
+        export(&db, ExportFormat::Csv, Some(&csv)).unwrap();
+        assert_eq!(import(&db, &csv, &Syntax::default(), false).unwrap(), 1);
+        run(&db, &parse_batch(">3,tea", &Syntax::default()).unwrap(), BASE_CURRENCY, false).unwrap();
+        let left = |db: &Path| list(db, Period::new(None, None), None, 10, false).unwrap().into_iter()
+            .map(|record| record.tr.category).collect::<Vec<_>>();
+        assert_eq!(undo(&db).unwrap().lines.len(), 1);
+        assert_eq!(left(&db), ["coffee", "coffee"]);
+        undo(&db).unwrap();
+        assert_eq!(left(&db), ["coffee"], "the imported row goes at once");
+        for path in [&db, &csv] {
+            std::fs::remove_file(path).unwrap();
+        }
+    }
+
+    #[test]
+    fn restore_backup_checks_before_replacing() {
+        let db = temp_db("restore-backup");
+        let copy = db.with_extension("copy.db");
+        let _ = std::fs::remove_file(&copy);
+        init(&db, BASE_CURRENCY, false).unwrap();
+        run(&db, &parse_batch("@2024-01-02 >5,tea;@2024-05-01 >6,tea", &Syntax::default()).unwrap(), BASE_CURRENCY, false).unwrap();
+        backup(&db, Some(&copy)).unwrap();
+        run(&db, &parse_batch(">7,tea", &Syntax::default()).unwrap(), BASE_CURRENCY, false).unwrap();
+
+        let (from, to) = check_backup(&db, &copy, false).unwrap();
+        assert_eq!(from.to_string(), "2 transaction(s) dated 2024-01-02..2024-05-01");
+        assert_eq!((from.version, to.unwrap().unwrap().count), (SCHEMA_VERSION, 3));
+        restore_backup(&db, &copy, false).unwrap();
+        assert_eq!(count_transactions(&db).unwrap(), 2);
+        assert!(!db.with_extension("restoring").exists());
+
+        let junk = db.with_extension("junk");
+        std::fs::write(&junk, "not a database at all, but long enough to look like one").unwrap();
+        assert!(matches!(restore_backup(&db, &junk, true), Err(LindaError::BadBackup(..))));
+        assert!(matches!(restore_backup(&db, &db.with_extension("missing"), true), Err(LindaError::BadBackup(..))));
+        assert_eq!(count_transactions(&db).unwrap(), 2, "left as it was");
+
+        let set_version = |version: usize| {
+            let conn = Connection::open(&copy).unwrap();
+            conn.execute("DELETE FROM schema_version", ()).unwrap();
+            conn.execute("INSERT INTO schema_version (version, applied_at) VALUES (?1, 0)", [version]).unwrap();
+        };
+        set_version(SCHEMA_VERSION - 1);
+        let err = check_backup(&db, &copy, false).unwrap_err();
+        assert!(err.to_string().contains("use --force to go back"), "{}", err);
+        assert!(check_backup(&db, &copy, true).is_ok());
+        set_version(SCHEMA_VERSION + 1);
+        let err = check_backup(&db, &copy, true).unwrap_err();
+        assert!(err.to_string().contains("newer than the"), "{}", err);
+        for path in [&db, &copy, &junk] {
+            std::fs::remove_file(path).unwrap();
+        }
+    }
+
+    #[test]
+    fn maintenance_compacts_a_sound_database() {
+        let db = temp_db("maintenance");
+        assert!(matches!(maintenance(&db), Err(LindaError::NotInitialized(_))));
+        init(&db, BASE_CURRENCY, false).unwrap();
+        let text = vec![">5,tea,a long description to take up some room"; 500].join(";");
+        run(&db, &parse_batch(&text, &Syntax::default()).unwrap(), BASE_CURRENCY, false).unwrap();
+        let mut conn = Connection::open(&db).unwrap();
+        conn.execute("UPDATE `transaction` SET deleted_at = 1 WHERE id > 1", ()).unwrap();
+        purge_rows(&mut conn).unwrap();
+        drop(conn);
+        let done = maintenance(&db).unwrap();
+        assert!(done.after < done.before, "{} -> {}", done.before, done.after);
+        let count = |name: &str| done.tables.iter().find(|(table, _)| table == name).map(|(_, count)| *count);
+        assert_eq!((count("transaction"), count("category")), (Some(1), Some(1)));
+        assert_eq!(count("sqlite_sequence"), None);
+
+        // Garble the pages after the first, where the rows are.
+        let mut bytes = std::fs::read(&db).unwrap();
+        for byte in &mut bytes[4096..] {
+            *byte ^= 0x5a;
+        }
+        std::fs::write(&db, bytes).unwrap();
+        assert!(maintenance(&db).is_err());
+        std::fs::remove_file(&db).unwrap();
+        let err = LindaError::Corrupt(String::from("row 3 missing from index"));
+        assert!(err.to_string().ends_with("`linda restore-backup <file>`"), "{}", err);
+    }
+
+    #[test]
+    fn connections_wait_for_a_writer() {
+        let db = temp_db("two-writers");
+        init(&db, BASE_CURRENCY, false).unwrap();
+        let conn = connect(&db).unwrap();
+        let mode: String = conn.query_row("PRAGMA journal_mode", [], |row| row.get(0)).unwrap();
+        let foreign_keys: bool = conn.query_row("PRAGMA foreign_keys", [], |row| row.get(0)).unwrap();
+        assert_eq!((mode.as_str(), foreign_keys), ("wal", true));
+
+        // One writer holds the database while the other tries to insert.
+        conn.execute_batch("BEGIN IMMEDIATE; INSERT INTO category (name) VALUES ('held');").unwrap();
+        let mut impatient = Connection::open(&db).unwrap();
+        impatient.busy_timeout(std::time::Duration::ZERO).unwrap();
+        assert!(store(&mut impatient, &[Cmd::from_str(">5,tea").unwrap()], BASE_CURRENCY, false).is_err(), "locked without a timeout");
+        let writer = {
+            let db = db.clone();
+            std::thread::spawn(move || run(&db, &[Cmd::from_str(">6,tea").unwrap()], BASE_CURRENCY, false))
+        };
+        std::thread::sleep(std::time::Duration::from_millis(300));
+        // Readers aren't held up meanwhile.
+        assert_eq!(count_transactions(&db).unwrap(), 0);
+        conn.execute_batch("COMMIT").unwrap();
+        writer.join().unwrap().unwrap();
+        assert_eq!(count_transactions(&db).unwrap(), 1);
+        drop((conn, impatient));
+        remove_wal(&db).unwrap();
+        std::fs::remove_file(&db).unwrap();
+    }
+
+    #[test]
+    fn resolve_db_makes_paths_absolute() {
+        let (db, warning) = resolve_db(Some(PathBuf::from("some/linda.db"))).unwrap();
+        assert_eq!((db, warning), (env::current_dir().unwrap().join("some/linda.db"), None));
+        assert_eq!(resolve_db(Some(PathBuf::from(MEMORY_DB))).unwrap().0, PathBuf::from(MEMORY_DB));
+        assert!(resolve_db(None).unwrap().0.is_absolute());
+    }
+
+    #[test]
+    fn delete_then_restore() {
+        let conn = memory_db();
+        insert(&conn, &Cmd::from_str(">120,coffee").unwrap(), BASE_CURRENCY, false).unwrap();
+        insert(&conn, &Cmd::from_str("&500,salary").unwrap(), BASE_CURRENCY, false).unwrap();
+        let listed = |include_deleted| -> Vec<(i64, bool)> {
+            Record::select(&conn, Period::new(None, None), None, 10, include_deleted).unwrap()
+                .iter().map(|record| (record.id, record.deleted_at.is_some())).collect()
+        };
+        delete_row(&conn, 1).unwrap();
+        assert_eq!(listed(false), [(2, false)]);
+        assert_eq!(listed(true), [(2, false), (1, true)]);
+        assert_eq!(Record::all(&conn).unwrap().len(), 1);
+        let totals = totals(&conn, Period::new(None, None), None, BASE_CURRENCY).unwrap();
+        assert_eq!((totals[0].expense, totals[0].net), (0, 50000), "deleted rows don't count");
+        assert!(matches!(delete_row(&conn, 1), Err(LindaError::NotFound(1))), "already deleted");
+
+        restore_row(&conn, 1).unwrap();
+        assert_eq!(listed(false), [(2, false), (1, false)]);
+        assert_eq!(Record::all(&conn).unwrap()[0].tr.tax, 12000);
+        assert!(matches!(restore_row(&conn, 1), Err(LindaError::NotDeleted(1))));
+        assert!(matches!(restore_row(&conn, 3), Err(LindaError::NotFound(3))));
+        assert_eq!(LindaError::NotDeleted(1).to_string(), "Transaction #1 isn't deleted");
+    }
+
+    #[test]
+    fn delete_several_all_or_none() {
+        let mut conn = memory_db();
+        store(&mut conn, &parse_batch(">1,a;>2,b;>3,c", &Syntax::default()).unwrap(), BASE_CURRENCY, false).unwrap();
+        let ids = |records: Vec<Record>| records.into_iter().map(|record| record.id).collect::<Vec<_>>();
+        assert_eq!(ids(find_undeleted(&conn, &[3, 1, 3]).unwrap()), [3, 1], "each once");
+        assert!(matches!(find_undeleted(&conn, &[1, 57]), Err(LindaError::NotFound(57))));
+        assert_eq!(LindaError::NotFound(57).to_string(), "No transaction #57");
+        assert!(matches!(delete_rows(&mut conn, &[1, 57, 2]), Err(LindaError::NotFound(57))));
+        assert_eq!(Record::all(&conn).unwrap().len(), 3, "a bad id deletes none");
+        delete_rows(&mut conn, &[1, 2, 1]).unwrap();
+        assert_eq!(ids(Record::all(&conn).unwrap()), [3]);
+        assert!(matches!(find_undeleted(&conn, &[2]), Err(LindaError::NotFound(2))), "already deleted");
+    }
+
+    #[test]
+    fn delete_then_purge() {
+        let mut conn = memory_db();
+        for text in [">120,coffee", ">80,tea", "&500,salary"] {
+            insert(&conn, &Cmd::from_str(text).unwrap(), BASE_CURRENCY, false).unwrap();
+        }
+        let category_id = |conn: &Connection, name: &str| -> i64 {
+            conn.query_row("SELECT id FROM category WHERE name = ?1", [name], |row| row.get(0)).unwrap()
+        };
+        let tea = category_id(&conn, "tea");
+        assert_eq!(purge_rows(&mut conn).unwrap(), 0, "nothing deleted yet");
+        delete_row(&conn, 2).unwrap();
+        delete_row(&conn, 3).unwrap();
+        assert_eq!(purge_rows(&mut conn).unwrap(), 2);
+        let count: i64 = conn.query_row("SELECT count(*) FROM `transaction`", [], |row| row.get(0)).unwrap();
+        assert_eq!(count, 1);
+        assert!(matches!(restore_row(&conn, 2), Err(LindaError::NotFound(2))), "purged for good");
+
+        // Ids of purged rows aren't handed out again, nor are those of their
+        // categories.
+        insert(&conn, &Cmd::from_str(">90,tea").unwrap(), BASE_CURRENCY, false).unwrap();
+        let ids: Vec<i64> = Record::all(&conn).unwrap().iter().map(|record| record.id).collect();
+        assert_eq!(ids, [1, 4]);
+        assert_eq!(category_id(&conn, "tea"), tea);
+    }
+
+    #[test]
+    fn edit_changes_only_given_fields() {
+        let mut conn = memory_db();
+        insert(&conn, &Cmd::from_str(">120,coffee,flat white").unwrap(), BASE_CURRENCY, false).unwrap();
+        let tea = Changes { category: Some(String::from("tea")), ..Changes::default() };
+        let record = edit_row(&mut conn, 1, &tea).unwrap();
+        assert_eq!((record.tr.tax, record.tr.category.as_str()), (12000, "tea"));
+        assert_eq!(record.tr.description.as_deref(), Some("flat white"));
+
+        let may_day = Local.with_ymd_and_hms(2024, 5, 1, 9, 30, 0).unwrap();
+        let changes = Changes {
+            kind: Some(OrderKind::Refund),
+            tax: Some(350),
+            category: Some(String::from("tea")),
+            description: Some(String::new()),
+            created_at: Some(may_day),
+        };
+        let before = Record::all(&conn).unwrap().remove(0);
+        assert_eq!(changes.diff(&before.tr), [
+            String::from("kind: expense -> refund"),
+            format!("amount: 120.00 {0} -> 3.50 {0}", BASE_CURRENCY),
+            String::from("description: 'flat white' -> ''"),
+            format!("date: {} -> 2024-05-01 09:30", before.tr.created_at.format("%Y-%m-%d %H:%M")),
+        ], "the category is tea already");
+        let record = edit_row(&mut conn, 1, &changes).unwrap();
+        assert_eq!((record.tr.kind, record.tr.tax, record.tr.description.as_deref()), (OrderKind::Refund, 350, None));
+        assert_eq!(record.tr.created_at, may_day);
+        assert!(changes.diff(&record.tr).is_empty());
+
+        let nothing = edit_row(&mut conn, 1, &Changes::default()).unwrap_err();
+        assert!(matches!(nothing, LindaError::NothingToChange));
+        let missing = edit_row(&mut conn, 7, &tea).unwrap_err();
+        assert!(matches!(missing, LindaError::NotFound(7)));
+        delete_row(&conn, 1).unwrap();
+        assert!(matches!(edit_row(&mut conn, 1, &tea), Err(LindaError::NotFound(1))), "deleted");
+    }
+
+    #[test]
+    fn separators_must_be_free_characters() {
+        for sep in ['&', '>', '+', ';', '"', '\\', '~', '@', '.', '-', '*', '/', '#', '%', 'a', '7', ' '] {
+            assert!(matches!(Syntax::with_sep(sep), Err(BadSepError(c, _)) if c == sep), "{:?}", sep);
+        }
+        let syntax = Syntax::with_sep('|').unwrap();
+        let tr = Tr::new(Cmd::parse_with(">350|food, delivery|~30", &syntax, Local::now()).unwrap(), BASE_CURRENCY).unwrap();
+        assert_eq!((tr.tax, tr.category.as_str(), tr.duration), (35000, "food, delivery", 30));
+    }
+
+    #[test]
+    fn report_nets_income_against_expense() {
+        let conn = memory_db();
+        for text in ["@2024-05-01 &1500,salary", "@2024-05-02 >120.50,groceries", "@2024-05-03 >30,lunch", "@2024-06-01 >999,rent"] {
+            insert(&conn, &Cmd::from_str(text).unwrap(), BASE_CURRENCY, false).unwrap();
+        }
+        let may = Period::new(NaiveDate::from_ymd_opt(2024, 5, 1), NaiveDate::from_ymd_opt(2024, 5, 31));
+        let may = &totals(&conn, may, None, BASE_CURRENCY).unwrap()[0];
+        assert_eq!((may.income, may.expense, may.net), (150000, 15050, 134950));
+        let all = &totals(&conn, Period::new(None, None), None, BASE_CURRENCY).unwrap()[0];
+        assert_eq!(all.net, 150000 - 15050 - 99900);
+        let june = Period::new(NaiveDate::from_ymd_opt(2024, 7, 1), None);
+        let nothing = &totals(&conn, june, None, "EUR").unwrap()[..];
+        assert!(matches!(nothing, [Totals { income: 0, expense: 0, net: 0, .. }]));
+        assert_eq!(nothing[0].currency, "EUR", "nothing recorded is zero in the base currency");
+    }
+
+    #[test]
+    fn json_output_reads_back() {
+        let conn = memory_db();
+        for text in ["@2024-05-01 &1500,salary", "@2024-05-02 >120.50,groceries,weekly #food"] {
+            insert(&conn, &Cmd::from_str(text).unwrap(), BASE_CURRENCY, false).unwrap();
+        }
+        let json = serde_json::to_string_pretty(&Record::select(&conn, Period::new(None, None), None, 10, false).unwrap()).unwrap();
+        let rows: serde_json::Value = serde_json::from_str(&json).unwrap();
+        assert_eq!(rows[0]["id"], 2);
+        assert_eq!(rows[0]["kind"], "expense");
+        assert_eq!(rows[0]["tax"], 12050);
+        assert_eq!(rows[0]["category"], "groceries");
+        assert_eq!(rows[0]["description"], "weekly");
+        assert_eq!(rows[0]["tags"], serde_json::json!(["food"]));
+        assert_eq!(rows[1]["description"], serde_json::Value::Null);
+        let created_at: DateTime<Local> = serde_json::from_value(rows[1]["created_at"].clone()).unwrap();
+        assert_eq!(created_at, Local.with_ymd_and_hms(2024, 5, 1, 12, 0, 0).unwrap());
+
+        let json = serde_json::to_string(&totals(&conn, Period::new(None, None), None, BASE_CURRENCY).unwrap()).unwrap();
+        let totals: serde_json::Value = serde_json::from_str(&json).unwrap();
+        assert_eq!(totals, serde_json::json!([{"currency": "USD", "income": 150000, "expense": 12050, "net": 137950}]));
+    }
+
+    /// Chile moves its clocks forward at midnight, run with
+    /// `TZ=America/Santiago` to start a day inside the gap.
+    #[test]
+    fn days_start_after_a_midnight_gap() {
+        let day = NaiveDate::from_ymd_opt(2024, 9, 8).unwrap();
+        let period = Period::new(Some(day), Some(day));
+        assert_eq!(from_timestamp(period.since).unwrap().date_naive(), day);
+        assert_eq!(period.since, Period::new(None, day.pred_opt()).until);
+        assert!(period.until - period.since >= 23 * 3600);
+    }
+
+    #[test]
+    fn amounts_must_be_positive() {
+        let invalid = |text| Cmd::from_str(text).unwrap().validate().unwrap_err();
+        assert!(matches!(invalid("&-100,food"), InvalidCmdError::InvalidAmount(-10000)));
+        assert!(matches!(invalid(">0,food"), InvalidCmdError::InvalidAmount(0)));
+        assert!(matches!(invalid("+-5,checking,savings"), InvalidCmdError::InvalidAmount(-500)));
+        assert_eq!(invalid(">-1.5,food").to_string(), "the amount must be positive, got -1.50; the sign is given by the modifier");
+        assert!(parse_batch(">12,food; &-100,food", &Syntax::default()).is_err());
+    }
+
+    #[test]
+    fn readme_quoting_example() {
+        let tr = Tr::new(Cmd::from_str(r#">350,"food, delivery","the ""good"" place""#).unwrap(), BASE_CURRENCY).unwrap();
+        assert_eq!((tr.category.as_str(), tr.description.as_deref()), ("food, delivery", Some(r#"the "good" place"#)));
+        let cmd = Cmd::from_str(r#"&100,"rent, march""#).unwrap();
+        assert_eq!(cmd.pack.len(), 3);
+        assert!(matches!(&cmd.pack[2], PartOfCmdKind::Word(w) if w == "rent, march"));
+        assert!(matches!(Cmd::from_str(r#"&100,"rent, march"#), Err(LindaError::Parse(ParseCmdError { kind: ParseErrorKind::UnterminatedQuote, pos: 5, .. }))));
+    }
+
+    #[test]
+    fn apostrophes_are_stored_intact() {
+        let conn = memory_db();
+        Tr::new(Cmd::from_str(">4,coffee's").unwrap(), BASE_CURRENCY).unwrap().insert(&conn).unwrap();
+        let category: String = conn.query_row("SELECT (SELECT name FROM category WHERE id = category_id) FROM `transaction`", [], |row| row.get(0)).unwrap();
+        assert_eq!(category, "coffee's");
+    }
+
+    #[test]
+    fn fields_are_checked_in_order() {
+        let invalid = |text| Cmd::from_str(text).unwrap().validate().unwrap_err();
+        assert!(matches!(invalid("&100"), InvalidCmdError::Missing(Field::Category, _)));
+        assert_eq!(invalid("&100,5").to_string(), "expected a category, found '5'");
+        assert!(Cmd::from_str("&100,lunch").unwrap().validate().is_ok());
+    }
+
+    #[test]
+    fn refunds_reduce_expenses() {
+        let conn = memory_db();
+        for text in ["&1000,salary", ">800,electronics", "-300,electronics"] {
+            insert(&conn, &Cmd::from_str(text).unwrap(), BASE_CURRENCY, false).unwrap();
+        }
+        let refund = Tr::new(Cmd::from_str("-300,electronics").unwrap(), BASE_CURRENCY).unwrap();
+        assert_eq!((refund.kind, refund.tax), (OrderKind::Refund, 30000));
+        let t = &totals(&conn, Period::new(None, None), None, BASE_CURRENCY).unwrap()[0];
+        assert_eq!((t.income, t.expense, t.net), (100000, 50000, 50000));
+    }
+
+    #[test]
+    fn rates_book_a_tax_share() {
+        let conn = memory_db();
+        let stored = insert(&conn, &Cmd::from_str("&50000%13,contract").unwrap(), BASE_CURRENCY, false).unwrap();
+        assert_eq!(stored.lines.len(), 2);
+        let rows: Vec<(String, i64, String, Option<String>)> = conn.prepare("SELECT kind, tax, (SELECT name FROM category WHERE id = category_id), description FROM `transaction` ORDER BY id").unwrap()
+            .query_map([], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?)))
+            .unwrap()
+            .collect::<Result<_>>()
+            .unwrap();
+        assert_eq!(rows, [
+            (String::from("income"), 5_000_000, String::from("contract"), None),
+            (String::from("expense"), 650_000, String::from("tax"), Some(String::from("13% of contract"))),
+        ]);
+        for (cents, rate, share) in [(33300, 300, 999), (33333, 300, 1000), (50, 1300, 7), (1, 5000, 1), (10000, 750, 750), (10000, 0, 0)] {
+            assert_eq!(share_of(cents, rate), share, "{} at {}", cents, rate);
+        }
+        let order = |text| match Cmd::from_str(text).unwrap().op {
+            Some(Op::Order(order)) => order.rate,
+            other => panic!("{:?}", other),
+        };
+        assert_eq!((order("&100,work,%7.5"), order("&100,work")), (Some(750), None));
+        assert!(matches!(Cmd::from_str("&100%101,work"), Err(LindaError::Parse(ParseCmdError { kind: ParseErrorKind::BadRate, .. }))));
+        assert!(matches!(Cmd::from_str(">100%5,food").unwrap().validate(), Err(InvalidCmdError::RateNotIncome('>'))));
+    }
+
+    #[test]
+    fn list_keeps_to_the_days_given() {
+        let db = temp_db("list-days");
+        init(&db, BASE_CURRENCY, false).unwrap();
+        run(&db, &parse_batch("@2024-05-01 >1,a; @2024-05-02 >2,b; @2024-05-03 >3,c", &Syntax::default()).unwrap(), BASE_CURRENCY, false).unwrap();
+        let day = |d| NaiveDate::from_ymd_opt(2024, 5, d);
+        let categories = |since, until| -> Vec<String> {
+            list(&db, Period::new(since, until), None, 10, false).unwrap().into_iter().map(|r| r.tr.category).collect()
+        };
+        assert_eq!(categories(day(2), day(2)), ["b"], "both ends are whole days");
+        assert_eq!(categories(day(2), None), ["c", "b"]);
+        assert_eq!(categories(None, day(1)), ["a"]);
+        assert!(categories(day(4), None).is_empty());
+        assert_eq!(list(&db, Period::new(None, None), None, 2, false).unwrap().len(), 2, "the limit still applies");
+        std::fs::remove_file(&db).unwrap();
+    }
+
+    #[test]
+    fn shorthands_expand_to_their_template() {
+        let db = temp_db("shorthand");
+        init(&db, BASE_CURRENCY, false).unwrap();
+        assert_eq!(shorthand(&db, "Coffee", ">1.80,a;>2,b", &Syntax::default()).map_err(|e| e.to_string()).unwrap_err(),
+            "Can't define shorthand 'coffee': the template must be a single command");
+        assert_eq!(shorthand(&db, "Coffee", ">1.80,Coffee,#daily", &Syntax::default()).unwrap(), ">1.80,coffee,#daily");
+        for (name, template) in [("coffee2", ">1,x"), ("expense", ">1,x"), ("tea", "@2024-05-01 >1,x"), ("tea", "coffee 2")] {
+            assert!(matches!(shorthand(&db, name, template, &Syntax::default()), Err(LindaError::BadShorthand(..))), "{} {}", name, template);
+        }
+        let syntax = Syntax { shorthands: shorthands(&db).unwrap(), ..Syntax::default() };
+        assert_eq!(syntax.shorthands.len(), 1);
+        let tr = |text| Tr::new(Cmd::parse_with(text, &syntax, Local::now()).unwrap(), BASE_CURRENCY).unwrap();
+        let plain = tr("coffee");
+        assert_eq!((plain.tax, plain.category.as_str(), plain.tags.as_slice()), (180, "coffee", [String::from("daily")].as_slice()));
+        let other = tr("Coffee 2.20 tea");
+        assert_eq!((other.tax, other.category.as_str(), other.tags.len()), (220, "tea", 1));
+        std::fs::remove_file(&db).unwrap();
+    }
+
+    #[test]
+    fn shorthands_of_a_database_not_set_up() {
+        let db = temp_db("no-shorthands");
+        assert!(shorthands(&db).unwrap().is_empty());
+        std::fs::File::create(&db).unwrap();
+        assert!(shorthands(&db).unwrap().is_empty());
+        assert_eq!(std::fs::metadata(&db).unwrap().len(), 0, "reading them writes nothing");
+        std::fs::remove_file(&db).unwrap();
+    }
+
+    #[test]
+    fn categories_keep_income_and_expense_apart() {
+        let db = temp_db("categories");
+        init(&db, BASE_CURRENCY, false).unwrap();
+        run(&db, &parse_batch("&100,gear;>30,gear;-5,gear;>40,food;>2,food", &Syntax::default()).unwrap(), BASE_CURRENCY, false).unwrap();
+        let rows = |by_total| -> Vec<(String, i64, i64, i64)> {
+            categories(&db, by_total).unwrap().rows.into_iter().map(|r| (r.category, r.count, r.income, r.expense)).collect()
+        };
+        let food = (String::from("food"), 2, 0, 4200);
+        let gear = (String::from("gear"), 3, 10000, 2500);
+        assert_eq!(rows(false), [food.clone(), gear.clone()]);
+        assert_eq!(rows(true), [gear, food]);
+        let json = serde_json::to_value(categories(&db, false).unwrap()).unwrap();
+        assert_eq!(json[1], serde_json::json!({"category": "gear", "count": 3, "income": 10000, "expense": 2500}));
+        std::fs::remove_file(&db).unwrap();
+    }
+
+    #[test]
+    fn rename_moves_or_merges_a_category() {
+        let db = temp_db("rename");
+        init(&db, BASE_CURRENCY, false).unwrap();
+        run(&db, &parse_batch(">5,cafe;>6,cafe;>7,food", &Syntax::default()).unwrap(), BASE_CURRENCY, false).unwrap();
+        budget(&db, "cafe", 1000).unwrap();
+        budget(&db, "sweets", 500).unwrap();
+        let names = || -> Vec<String> { categories(&db, false).unwrap().rows.into_iter().map(|r| r.category).collect() };
+        assert_eq!(rename_category(&db, "cafe", "food").unwrap(), 2);
+        assert_eq!(names(), ["food"]);
+        let limit = |category| Connection::open(&db).unwrap()
+            .query_row("SELECT monthly_limit FROM budget WHERE category = ?1", [category], |row| row.get::<_, i64>(0))
+            .optional().unwrap();
+        assert_eq!(limit("food"), Some(1000), "the budget went along");
+        assert_eq!(rename_category(&db, "sweets", "treats").unwrap(), 0, "a budget alone is enough to rename");
+        assert_eq!(limit("treats"), Some(500));
+        assert!(matches!(rename_category(&db, "nope", "food"), Err(LindaError::UnknownCategory(name)) if name == "nope"));
+        assert_eq!(names(), ["food"], "nothing was added");
+        std::fs::remove_file(&db).unwrap();
+    }
+
+    #[test]
+    fn sql_in_fields_is_stored_verbatim() {
+        let db = temp_db("injection");
+        init(&db, BASE_CURRENCY, false).unwrap();
+        let exact = Syntax { normalize: false, ..Syntax::default() };
+        run(&db, &parse_batch(r#">5,"mom's gift; DROP TABLE tag; --","'); DELETE FROM budget; --",#x'y"#, &exact).unwrap(), BASE_CURRENCY, false).unwrap();
+        let tr = &list(&db, Period::new(None, None), None, 10, false).unwrap()[0].tr;
+        assert_eq!(tr.category, "mom's gift; DROP TABLE tag; --");
+        assert_eq!(tr.description.as_deref(), Some("'); DELETE FROM budget; --"));
+        assert_eq!(tr.tags, ["x'y"]);
+        std::fs::remove_file(&db).unwrap();
+    }
+
+    #[test]
+    fn created_at_is_stored_as_unix_seconds() {
+        let db = temp_db("created-at");
+        init(&db, BASE_CURRENCY, false).unwrap();
+        run(&db, &parse_batch("@2024-05-01 >5,food", &Syntax::default()).unwrap(), BASE_CURRENCY, false).unwrap();
+        let (kind, seconds): (String, i64) = Connection::open(&db).unwrap()
+            .query_row("SELECT typeof(created_at), created_at FROM `transaction`", [], |row| Ok((row.get(0)?, row.get(1)?)))
+            .unwrap();
+        assert_eq!((kind.as_str(), seconds), ("integer", Local.with_ymd_and_hms(2024, 5, 1, 12, 0, 0).unwrap().timestamp()));
+        std::fs::remove_file(&db).unwrap();
+    }
+
+    #[test]
+    fn run_reports_nothing_unless_all_is_committed() {
+        let db = temp_db("all-or-nothing");
+        init(&db, BASE_CURRENCY, false).unwrap();
+        let cmds = parse_batch(">5,food; >6,drinks", &Syntax::default()).unwrap();
+        assert_eq!(run(&db, &cmds, BASE_CURRENCY, false).unwrap().lines.len(), 2);
+        assert_eq!(run(&db, &cmds, BASE_CURRENCY, false).unwrap().lines.len(), 2, "the commands can be run again");
+        Connection::open(&db).unwrap().execute_batch("DROP TABLE transaction_tag").unwrap();
+        let err = run(&db, &parse_batch(">7,food; >8,drinks,#late", &Syntax::default()).unwrap(), BASE_CURRENCY, false).unwrap_err();
+        assert!(matches!(err, LindaError::Database(_)), "{}", err);
+        let count: i64 = Connection::open(&db).unwrap().query_row("SELECT COUNT(*) FROM `transaction`", [], |row| row.get(0)).unwrap();
+        assert_eq!(count, 4, "the first command was rolled back");
+        std::fs::remove_file(&db).unwrap();
+    }
+
+    #[test]
+    fn batches_name_the_bad_command() {
+        assert_eq!(parse_batch(r#"&100,10,salary; >20,"a;b"; >5,x;"#, &Syntax::default()).unwrap().len(), 3);
+        for (text, index, bad, reason) in [
+            (">5,food; >x,tea; >6,milk", 2, ">x,tea", "expected an amount"),
+            (">5,food;; >6,milk", 2, "", "the command line is empty"),
+            ("+5,cash,bank; +5,cash,cash", 2, "+5,cash,cash", "two different accounts"),
+        ] {
+            let err = parse_batch(text, &Syntax::default()).unwrap_err();
+            assert_eq!((err.index, err.text.as_str()), (index, bad), "{}", text);
+            assert!(err.reason.contains(reason), "{}: {}", text, err.reason);
+        }
+    }
+
+    #[test]
+    fn lines_are_read_one_command_each() {
+        let cmds = parse_lines("\u{feff}>5,food\n\n  \n&100,salary; >2,tea\r\n", &Syntax::default()).unwrap();
+        let taxes: Vec<i64> = cmds.into_iter().map(|cmd| Tr::new(cmd, BASE_CURRENCY).unwrap().tax).collect();
+        assert_eq!(taxes, [500, 10000, 200]);
+        let err = parse_lines(">5,food\n\n>6,tea; >x,milk\n", &Syntax::default()).unwrap_err();
+        assert_eq!((err.line, err.index, err.text.as_str()), (Some(3), 2, ">x,milk"));
+        assert!(err.to_string().starts_with("Line 3 '>x,milk' failed: "), "{}", err);
+    }
+
+    #[test]
+    fn exec_needs_an_initialized_database() {
+        let db = temp_db("missing");
+        let err = run(&db, &parse_batch(">5,food", &Syntax::default()).unwrap(), BASE_CURRENCY, false).unwrap_err();
+        assert!(matches!(&err, LindaError::NotInitialized(path) if path == &db), "{}", err);
+        assert!(!db.exists(), "no empty file is left behind");
+        std::fs::write(&db, "").unwrap();
+        let err = run(&db, &parse_batch(">5,food", &Syntax::default()).unwrap(), BASE_CURRENCY, false).unwrap_err();
+        assert_eq!(err.to_string(), format!("There is no linda database at {}, run `linda init` first", db.display()));
+        std::fs::remove_file(&db).unwrap();
+    }
+
+    #[test]
+    fn budgets_warn_once_exceeded() {
+        let db = temp_db("budget");
+        init(&db, BASE_CURRENCY, false).unwrap();
+        budget(&db, "food", 10000).unwrap();
+        let exec = |text| run(&db, &parse_batch(text, &Syntax::default()).unwrap(), BASE_CURRENCY, false).unwrap().warnings;
+        assert!(exec(">60,food;>30,drinks").is_empty());
+        assert!(exec(">40,food").is_empty(), "at the limit");
+        let over = exec(">0.01,food;-5,food;&10,food");
+        assert_eq!(over, [format!("food is over its budget for {}: 100.01 of 100.00", Local::now().format("%Y-%m"))]);
+        assert_eq!(list(&db, Period::new(None, None), None, 10, false).unwrap().len(), 6, "over budget expenses are still stored");
+        std::fs::remove_file(&db).unwrap();
+    }
+
+    #[test]
+    fn amounts_take_a_currency_code() {
+        let tr = |text| Tr::new(Cmd::from_str(text).unwrap(), BASE_CURRENCY).unwrap();
+        assert_eq!((tr(">45EUR,hotel").tax, tr(">45eur,hotel").currency), (4500, String::from("EUR")));
+        assert_eq!(tr(">45,hotel").currency, BASE_CURRENCY);
+        assert_eq!(tr(">2k,rent").tax, 200000, "a single letter is still a multiplier");
+        assert!(matches!(
+            Cmd::from_str(">45EURO,x"),
+            Err(LindaError::Parse(ParseCmdError { kind: ParseErrorKind::BadCurrency, .. }))
+        ));
+        let err = Cmd::from_str("&abc,food").unwrap_err().to_string();
+        assert!(err.contains("abc"), "{}", err);
+    }
+
+    #[test]
+    fn run_describes_rows_and_keeps_dumps_apart() {
+        let db = temp_db("dumps");
+        init(&db, BASE_CURRENCY, false).unwrap();
+        let stored = run(&db, &parse_batch("@2024-05-01 >5,food,lunch #team; @2024-05-01 +2,cash,bank", &Syntax::default()).unwrap(), BASE_CURRENCY, true).unwrap();
+        assert_eq!(stored.lines, ["Recorded expense #1: 5.00 USD food, lunch #team (2024-05-01)", "Recorded transfer #1: 2.00 cash -> bank (2024-05-01)"]);
+        let euros = run(&db, &parse_batch("@2024-05-01 &3EUR,tips", &Syntax::default()).unwrap(), BASE_CURRENCY, false).unwrap();
+        assert_eq!(euros.lines, ["Recorded income #2: 3.00 EUR tips (2024-05-01)"], "ids go on from the last run");
+        assert_eq!(stored.debug.len(), 4, "each command and each row: {:?}", stored.debug);
+        assert!(stored.debug[0].starts_with("Cmd {") && stored.debug[1].starts_with("Tr {"), "{:?}", stored.debug);
+        assert!(stored.debug[3].starts_with("Transfer {"), "{:?}", stored.debug);
+        std::fs::remove_file(&db).unwrap();
+    }
+
+    #[test]
+    fn stored_rows_have_their_ids() {
+        let mut conn = memory_db();
+        store(&mut conn, &parse_batch(">1,a", &Syntax::default()).unwrap(), BASE_CURRENCY, false).unwrap();
+        let stored = store(&mut conn, &parse_batch("&100%10,gig;+5,default,savings", &Syntax::default()).unwrap(), BASE_CURRENCY, true).unwrap();
+        let rows: Vec<_> = stored.rows.iter().map(|row| (row.id, row.kind.as_str())).collect();
+        assert_eq!(rows, [(2, "income"), (3, "expense"), (1, "transfer")]);
+        let selected: Vec<_> = Record::query(&conn, "WHERE id >= 2 ORDER BY id", []).unwrap().into_iter()
+            .map(|record| (record.id, record.tr.category)).collect();
+        assert_eq!(selected, [(2, String::from("gig")), (3, String::from("tax"))]);
+        assert_eq!(serde_json::to_string(&stored.rows[0]).unwrap(), r#"{"id":2,"kind":"income"}"#);
+    }
+
+    #[test]
+    fn dry_run_shows_the_sql_it_would_run() {
+        let cmds = parse_batch(r#"@2024-05-01 &1000,salary,"Bob's",%25 #work"#, &Syntax::default()).unwrap();
+        let sql: Vec<String> = dry_run(&cmds, BASE_CURRENCY).unwrap().into_iter().filter(|l| l.starts_with("sql: ")).collect();
+        let noon = Local.with_ymd_and_hms(2024, 5, 1, 12, 0, 0).unwrap().timestamp();
+        assert_eq!(sql.len(), 10, "the income and its tax share, each with a category, an account and a tag: {:?}", sql);
+        assert_eq!(sql[0], "sql: INSERT OR IGNORE INTO category (name) VALUES ('salary')");
+        assert_eq!(sql[1], "sql: INSERT OR IGNORE INTO account (name) VALUES ('default')");
+        assert_eq!(sql[2], format!(
+            "sql: INSERT INTO `transaction` (created_at, kind, tax, category_id, description, duration, currency, account_id) \
+             VALUES ({}, 'income', 100000, (SELECT id FROM category WHERE name = 'salary'), 'Bob''s', 0, 'USD', \
+             (SELECT id FROM account WHERE name = 'default'))", noon
+        ));
+        assert!(sql[7].contains("VALUES ({}, 'expense', 25000, (SELECT id FROM category WHERE name = 'tax'), '25% of salary', 0, 'USD', (SELECT".replace("{}", &noon.to_string()).as_str()), "{}", sql[7]);
+        assert!(sql[4].contains("SELECT '<new id>', id FROM tag WHERE name = 'work'"), "{}", sql[4]);
+        let transfer = dry_run(&parse_batch("+5,cash,bank", &Syntax::default()).unwrap(), BASE_CURRENCY).unwrap();
+        assert!(transfer.last().unwrap().starts_with("sql: INSERT INTO transfer"), "{:?}", transfer);
+    }
+
+    #[test]
+    fn report_totals_each_currency_apart() {
+        let db = temp_db("currencies");
+        init(&db, BASE_CURRENCY, false).unwrap();
+        run(&db, &parse_batch(">5,food", &Syntax::default()).unwrap(), BASE_CURRENCY, false).unwrap();
+        assert!(balance(&db, None).unwrap().warnings.is_empty());
+        run(&db, &parse_batch(">45EUR,hotel;&100EUR,refund;>2.50,tea", &Syntax::default()).unwrap(), BASE_CURRENCY, false).unwrap();
+        let totals = report(&db, Period::new(None, None), None, BASE_CURRENCY).unwrap().into_iter()
+            .map(|t| (t.currency, t.income, t.expense, t.net)).collect::<Vec<_>>();
+        assert_eq!(totals, [(String::from("EUR"), 10000, 4500, 5500), (String::from("USD"), 0, 750, -750)]);
+        let warnings = balance(&db, None).unwrap().warnings;
+        assert_eq!(warnings, ["the totals mix amounts in EUR, USD without converting them"]);
+        assert_eq!(categories(&db, false).unwrap().warnings, warnings);
+        std::fs::remove_file(&db).unwrap();
+    }
+
+    #[test]
+    fn balance_nets_everything() {
+        let db = temp_db("balance");
+        init(&db, BASE_CURRENCY, false).unwrap();
+        assert_eq!(balance(&db, None).unwrap().balance, 0);
+        run(&db, &parse_batch("&1000,salary;>800,electronics;-300,electronics;>0.5,tea", &Syntax::default()).unwrap(), BASE_CURRENCY, false).unwrap();
+        assert_eq!(balance(&db, None).unwrap().balance, 49950);
+        std::fs::remove_file(&db).unwrap();
+    }
+
+    #[test]
+    fn unknown_modifiers_are_errors() {
+        let pack = [PartOfCmdKind::Mod('%'), PartOfCmdKind::Digit(500), PartOfCmdKind::Word(String::from("tea"))];
+        assert!(matches!(Cmd::read_op(&pack, &None), Err(LindaError::UnknownOrderKind('%'))));
+        let cmd = Cmd { pack: pack.into(), created_at: Local::now(), tags: vec![], op: None, currency: None, account: None };
+        assert!(matches!(cmd.kind_of(), Err(LindaError::UnknownOrderKind('%'))));
+        assert!(matches!(Cmd::from_str("+5,checking").unwrap().kind_of(), Ok(None)));
+    }
+
+    #[test]
+    fn parts_convert_or_say_what_they_are() {
+        let word = PartOfCmdKind::Word(String::from("abc"));
+        assert_eq!(i64::try_from(&word).unwrap_err().to_string(), "expected an amount, found 'abc'");
+        assert_eq!(i32::try_from(&word).unwrap_err().to_string(), "expected a duration, found 'abc'");
+        assert_eq!(String::try_from(&PartOfCmdKind::Digit(500)).unwrap_err().to_string(), "expected a word, found '5'");
+        assert_eq!((i64::try_from(&PartOfCmdKind::Digit(500)).unwrap(), String::try_from(&word).unwrap()), (500, String::from("abc")));
+        assert_eq!(i32::try_from(&PartOfCmdKind::Duration(30)).unwrap(), 30);
+        let err = Tr::new(Cmd::from_str("+5,checking,savings").unwrap(), BASE_CURRENCY).unwrap_err();
+        assert!(matches!(err, LindaError::Unsupported(_)), "{:?}", err);
+    }
+
+    #[test]
+    fn transfers_move_between_two_accounts() {
+        let cmd = Cmd::from_str("+500,checking,savings").unwrap();
+        assert!(cmd.validate().is_ok());
+        let transfer = Transfer::new(cmd).unwrap();
+        assert_eq!((transfer.tax, transfer.source.as_str(), transfer.target.as_str()), (50000, "checking", "savings"));
+        let same = Cmd::from_str("+5,main,main").unwrap().validate().unwrap_err();
+        assert_eq!(same.to_string(), "a transfer needs two different accounts, not 'main' twice");
+        assert!(matches!(Cmd::from_str("+500,checking").unwrap().validate(), Err(InvalidCmdError::Missing(Field::Target, _))));
+        assert!(matches!(Cmd::from_str("%500,checking"), Err(LindaError::Parse(ParseCmdError { kind: ParseErrorKind::UnexpectedMod, .. }))));
+    }
+
+    #[test]
+    fn tables_align_every_row() {
+        let rows = [["1", "2024-05-01", "5.00 USD", "tea"], ["12", "2024-05-02", "1250.00 USD", "groceries"], ["7", "", "0.50 USD", ""]];
+        let rows: Vec<Vec<String>> = rows.iter().map(|r| r.iter().map(|c| c.to_string()).collect()).collect();
+        let lines = render_table(&["id", "date", "amount", "category"], &[0, 2], &rows);
+        assert_eq!(lines, [
+            "id  date             amount  category",
+            "--  ----------  -----------  ---------",
+            " 1  2024-05-01     5.00 USD  tea",
+            "12  2024-05-02  1250.00 USD  groceries",
+            " 7                 0.50 USD",
+        ]);
+        assert_eq!(render_table(&["id", "category"], &[0], &[]), ["id  category", "--  --------"]);
+    }
+
+    #[test]
+    fn stats_give_count_min_max_and_average() {
+        let db = temp_db("stats");
+        init(&db, BASE_CURRENCY, false).unwrap();
+        let text = "@2024-05-01 >10,food;@2024-05-02 >2.50,food;@2024-05-03 >0.01,food;@2024-05-04 >99,rent;\
+                    @2024-05-05 &1000,salary;@2024-05-06 >8EUR,food;@2024-06-01 >50,food";
+        run(&db, &parse_batch(text, &Syntax::default()).unwrap(), BASE_CURRENCY, false).unwrap();
+        let may = Period::new(NaiveDate::from_ymd_opt(2024, 5, 1), NaiveDate::from_ymd_opt(2024, 5, 31));
+        let stats = |category, period| stats(&db, category, period, None).unwrap().into_iter()
+            .map(|s| (s.kind, s.currency, s.count, s.min, s.max, s.average)).collect::<Vec<_>>();
+        assert_eq!(stats(Some("food"), may), [
+            (OrderKind::Expense, String::from("EUR"), 1, 800, 800, 800),
+            (OrderKind::Expense, String::from("USD"), 3, 1, 1000, 417),
+        ]);
+        assert_eq!(stats(None, may), [
+            (OrderKind::Expense, String::from("EUR"), 1, 800, 800, 800),
+            (OrderKind::Expense, String::from("USD"), 4, 1, 9900, 2788),
+            (OrderKind::Income, String::from("USD"), 1, 100000, 100000, 100000),
+        ]);
+        assert!(stats(Some("travel"), may).is_empty());
+        std::fs::remove_file(&db).unwrap();
+    }
+
+    #[test]
+    fn in_memory_runs_leave_no_file() {
+        let cmds = parse_batch("&100,salary;>12.50EUR,lunch;+5,cash,bank", &Syntax::default()).unwrap();
+        let (stored, records) = run_in_memory(&cmds, BASE_CURRENCY, true).unwrap();
+        assert_eq!(stored.lines.len(), 3);
+        let rows: Vec<_> = records.iter().map(|r| (r.tr.kind, r.tr.tax, r.tr.currency.as_str())).collect();
+        assert_eq!(rows, [(OrderKind::Expense, 1250, "EUR"), (OrderKind::Income, 10000, "USD")]);
+        assert!(!Path::new(MEMORY_DB).exists());
+        let (again, _) = run_in_memory(&cmds[..1], BASE_CURRENCY, false).unwrap();
+        assert_eq!(again.lines, [stored.lines[0].clone()], "each run starts empty");
+    }
+
+    #[test]
+    fn paint_colors_only_when_asked() {
+        assert_eq!(paint("5.00", Style::Green, true), "\x1b[32m5.00\x1b[0m");
+        assert_eq!(paint("5.00", Style::Red, true), "\x1b[31m5.00\x1b[0m");
+        assert_eq!(paint("net", Style::Bold, true), "\x1b[1mnet\x1b[0m");
+        assert_eq!(paint("5.00", Style::Red, false), "5.00");
+    }
+
+    #[test]
+    fn accounts_filter_list_report_and_balance() {
+        let db = temp_db("accounts");
+        init(&db, BASE_CURRENCY, false).unwrap();
+        let mut cmds = parse_batch("&100,salary;>30,rent", &Syntax::default()).unwrap();
+        cmds[0].account = Some(String::from("checking"));
+        let cash = parse_batch(">5,tea;+20,checking,cash", &Syntax::default()).unwrap();
+        cmds.extend(cash);
+        run(&db, &cmds, BASE_CURRENCY, true).unwrap();
+        let accounts = |account| list(&db, Period::new(None, None), account, 10, false).unwrap()
+            .into_iter().map(|r| (r.tr.tax, r.tr.account)).collect::<Vec<_>>();
+        assert_eq!(accounts(Some("checking")), [(10000, String::from("checking"))]);
+        assert_eq!(accounts(Some(DEFAULT_ACCOUNT)).len(), 2);
+        assert_eq!(accounts(None).len(), 3);
+        let totals = &report(&db, Period::new(None, None), Some(DEFAULT_ACCOUNT), BASE_CURRENCY).unwrap()[0];
+        assert_eq!((totals.income, totals.expense), (0, 3500));
+        assert_eq!(balance(&db, Some("checking")).unwrap().balance, 8000);
+        assert_eq!(balance(&db, Some("cash")).unwrap().balance, 2000);
+        assert_eq!(balance(&db, None).unwrap().balance, 6500);
+        std::fs::remove_file(&db).unwrap();
+    }
+
+    #[test]
+    fn errors_display_their_cause() {
+        let parse = Cmd::from_str("&lunch,100").unwrap_err();
+        assert_eq!(parse.to_string(), "unexpected token 'lunch' at position 1: expected an amount like 12, 12.50 or 1.2k");
+        let batch = parse_batch(">5,tea;%", &Syntax::default()).unwrap_err();
+        let io = || std::io::Error::new(std::io::ErrorKind::NotFound, "gone");
+        let cases = [
+            (OrderKind::new('+').unwrap_err(), "'+' is not an order modifier, expected &, > or -"),
+            (InvalidCmdError::InvalidAmount(0).into(), "the amount must be positive, got 0.00; the sign is given by the modifier"),
+            (batch.into(), "Command 2 '%' failed: unknown command type '%' at position 0, did you mean '>' (expense)? Modifiers: & income, > expense, + transfer, - refund"),
+            (LindaError::Unsupported(String::from("> Digit")), "Unrecognized command shape: > Digit"),
+            (LindaError::NotFound(7), "No transaction #7"),
+            (LindaError::NotInitialized(PathBuf::from("x.db")), "There is no linda database at x.db, run `linda init` first"),
+            (LindaError::UnknownCategory(String::from("tea")), "There is no category named 'tea'"),
+            (LindaError::BadShorthand(String::from("tea"), String::from("why")), "Can't define shorthand 'tea': why"),
+            (LindaError::NothingToChange, "Nothing was changed, specify at least one field to edit"),
+            (ImportError::Row(3, String::from("bad")).into(), "Line 3: bad"),
+            (rusqlite::Error::QueryReturnedNoRows.into(), "Database error: Query returned no rows"),
+            (io().into(), "gone"),
+            (csv::Error::from(io()).into(), "CSV error: gone"),
+            (serde_json::from_str::<i64>("x").unwrap_err().into(), "JSON error: expected value at line 1 column 1"),
+        ];
+        for (err, text) in cases {
+            assert_eq!(err.to_string(), text, "{:?}", err);
+        }
+        assert_eq!(
+            LindaError::OutdatedSchema(2).to_string(),
+            format!("The database is at schema version 2 and needs to be at {}, run `linda init` to upgrade it", SCHEMA_VERSION)
+        );
+        assert_eq!(
+            LindaError::NewerSchema(99).to_string(),
+            format!("The database is at schema version 99, newer than the {} this linda knows, upgrade linda to use it", SCHEMA_VERSION)
+        );
+    }
+
+    #[test]
+    fn unknown_command_types_suggest_a_modifier() {
+        let message = |text| Cmd::from_str(text).unwrap_err().to_string();
+        assert!(message("<5,food").contains("did you mean '>' (expense)?"), "{}", message("<5,food"));
+        assert!(message("=5,cash,bank").contains("did you mean '+' (transfer)?"));
+        assert!(message("\u{2013}5,food").contains("did you mean '-' (refund)?"));
+        let letter = message("x5,food");
+        assert!(letter.contains("unknown command type 'x5,food' at position 0. Modifiers:"), "{}", letter);
+    }
+
+    #[test]
+    fn bad_modifiers_are_echoed_whole() {
+        let token = |text: &str| match Cmd::from_str(text) {
+            Err(LindaError::Parse(ParseCmdError { kind: ParseErrorKind::UnexpectedMod, token, pos })) => (token, pos),
+            other => panic!("{} parsed as {:?}", text, other),
+        };
+        for bad in ["\u{1f1e9}\u{1f1ea}", "\u{1f469}\u{200d}\u{1f4bb}", "\u{1f44d}\u{1f3fd}", "#\u{fe0f}\u{20e3}", "=\u{0301}"] {
+            assert_eq!(token(&format!("{}100,x", bad)), (String::from(bad), 0));
+        }
+        let flag = Cmd::from_str("\u{1f1e9}\u{1f1ea}100,x").unwrap_err().to_string();
+        assert!(flag.starts_with("unknown command type '\u{1f1e9}\u{1f1ea}' at position 0"), "{}", flag);
+    }
+
+    /// The categories of `text` once stored and read back.
+    fn stored_categories(text: &str, syntax: &Syntax) -> Vec<String> {
+        let mut conn = memory_db();
+        let tx = conn.transaction().unwrap();
+        for cmd in parse_batch(text, syntax).unwrap() {
+            insert(&tx, &cmd, BASE_CURRENCY, false).unwrap();
+        }
+        tx.commit().unwrap();
+        Record::select(&conn, Period::new(None, None), None, 100, false).unwrap().into_iter().rev().map(|record| record.tr.category).collect()
+    }
+
+    #[test]
+    fn non_ascii_categories_round_trip() {
+        let exact = Syntax { normalize: false, ..Syntax::default() };
+        assert_eq!(stored_categories(">3,кофе;>4,café;>5,🍕 pizza;>6,👩‍💻", &exact), ["кофе", "café", "🍕 pizza", "👩‍💻"]);
+        assert_eq!(stored_categories(">3,КОФЕ;>4,Café", &Syntax::default()), ["кофе", "café"]);
+    }
+
+    #[test]
+    fn byte_order_mark_is_skipped() {
+        assert_eq!(stored_categories("\u{feff}>3,кофе", &Syntax::default()), ["кофе"]);
+    }
+
+    #[test]
+    fn modifiers_can_be_spelled_out() {
+        let tr = |text| Tr::new(Cmd::from_str(text).unwrap(), BASE_CURRENCY).unwrap();
+        let shop = tr("Expense 250 groceries weekly shop");
+        assert_eq!((shop.kind, shop.tax, shop.category.as_str()), (OrderKind::Expense, 25000, "groceries"));
+        assert_eq!(shop.description.as_deref(), Some("weekly shop"));
+        let paid = tr("income 200 90 consulting for ACME");
+        assert_eq!((paid.duration, paid.description.as_deref()), (90, Some("for ACME")));
+        let lunch = tr("expense 12 \"take out\" lunch ~45");
+        assert_eq!((lunch.category.as_str(), lunch.duration), ("take out", 45));
+        let moved = Transfer::new(Cmd::from_str("transfer 5 cash bank").unwrap()).unwrap();
+        assert_eq!((moved.source.as_str(), moved.target.as_str()), ("cash", "bank"));
+        assert!(matches!(
+            Cmd::from_str("spend 5 food"),
+            Err(LindaError::Parse(ParseCmdError { kind: ParseErrorKind::UnexpectedMod, .. }))
+        ));
+    }
+
+    #[test]
+    fn empty_fields_are_errors() {
+        let tr = |text| Tr::new(Cmd::from_str(text).unwrap(), BASE_CURRENCY).unwrap();
+        let trailing = tr(">5,food,lunch,");
+        assert_eq!((trailing.tax, trailing.category.as_str(), trailing.description.as_deref()), (500, "food", Some("lunch")));
+        assert_eq!(tr(r#">5,food,"""#).description.as_deref(), Some(""), "an empty field on purpose");
+        for (text, field, pos) in [(">5,,food", 2, 3), (">,5,food", 1, 1), ("&5,food,,x,", 3, 8)] {
+            match Cmd::from_str(text) {
+                Err(LindaError::Parse(err)) => {
+                    assert_eq!(err.kind, ParseErrorKind::EmptyField(field), "{}", text);
+                    assert_eq!(err.pos, pos, "{}", text);
+                },
+                other => panic!("{}: {:?}", text, other),
+            }
+        }
+        assert_eq!(Cmd::from_str(">5,,food").unwrap_err().to_string(), "field 2 is empty at position 3");
+    }
+
+    #[test]
+    fn blank_categories_are_missing() {
+        let invalid = |text| Cmd::from_str(text).unwrap().validate().unwrap_err();
+        // The trailing comma is dropped, which leaves the minutes where the
+        // category goes.
+        assert_eq!(invalid("&100,10,").to_string(), "expected a category, found '10'");
+        assert!(matches!(Cmd::from_str(">5,   ,lunch"), Err(LindaError::Parse(ParseCmdError { kind: ParseErrorKind::EmptyField(2), .. }))));
+        for text in [r#">5,"""#, r#">5,"   ",lunch"#] {
+            assert!(matches!(invalid(text), InvalidCmdError::Missing(Field::Category, _)), "{}", text);
+        }
+        assert!(matches!(invalid(r#"+5,"",savings"#), InvalidCmdError::Missing(Field::Source, _)));
+        assert!(matches!(invalid(r#"+5,checking," ""#), InvalidCmdError::Missing(Field::Target, _)));
+        assert_eq!(invalid(r#">5," ""#).to_string(), format!("missing category, expected {}", ORDER_SHAPE));
+
+        let row = csv::StringRecord::from(vec!["1", "2024-05-01T12:00:00+00:00", "12.50", "  ", "0", "", "expense", "USD", "default"]);
+        assert_eq!(Tr::from_csv(&row, &Syntax::default()).unwrap_err(), "the category is empty");
+    }
+
+    #[test]
+    fn extra_fields_are_rejected() {
+        let err = Cmd::from_str("&100,food,whatever,else,more").unwrap().validate().unwrap_err();
+        assert_eq!(err.to_string(), format!("unexpected field 'else' (position 4), expected {}", ORDER_SHAPE));
+        assert!(matches!(Cmd::from_str(">5,tea,~10,~20").unwrap().validate(), Err(InvalidCmdError::Unexpected(4, _, ORDER_SHAPE))));
+        assert!(matches!(Cmd::from_str("+5,checking,savings,why").unwrap().validate(), Err(InvalidCmdError::Unexpected(4, _, TRANSFER_SHAPE))));
+        assert!(Cmd::from_str(">5,tea,~10,with bob").unwrap().validate().is_ok());
+    }
+
+    #[test]
+    fn packs_of_the_wrong_length_name_the_shape() {
+        let conn = memory_db();
+        for (text, shape) in [("&100,food,~10,a,b,c", ORDER_SHAPE), ("&100", ORDER_SHAPE), ("+5,checking", TRANSFER_SHAPE), ("+5", TRANSFER_SHAPE)] {
+            let cmd = Cmd::from_str(text).unwrap();
+            match cmd.validate() {
+                Err(InvalidCmdError::Missing(_, found) | InvalidCmdError::Unexpected(_, _, found)) => assert_eq!(found, shape, "{}", text),
+                other => panic!("{}: {:?}", text, other),
+            }
+            // Refused all the same when it isn't validated first.
+            assert!(insert(&conn, &cmd, BASE_CURRENCY, false).is_err(), "{}", text);
+            assert!(parse_batch(text, &Syntax::default()).is_err(), "{}", text);
+        }
+        assert_eq!(
+            Cmd::from_str("+5,checking").unwrap().validate().unwrap_err().to_string(),
+            "missing target, expected +<amount>,<source>,<target>"
+        );
+    }
+
+    #[test]
+    fn commands_read_into_typed_fields() {
+        let order = |kind, amount, category: &str, description: Option<&str>, duration| Some(Op::Order(Order {
+            kind, amount, category: String::from(category), description: description.map(String::from), duration, rate: None, currency: None,
+        }));
+        let cases = [
+            ("&1500,salary", order(OrderKind::Income, 150000, "salary", None, 0)),
+            (">12.50,lunch,with bob", order(OrderKind::Expense, 1250, "lunch", Some("with bob"), 0)),
+            (">12.50,lunch,~30,with bob", order(OrderKind::Expense, 1250, "lunch", Some("with bob"), 30)),
+            (">12.50,lunch,with bob,~30", order(OrderKind::Expense, 1250, "lunch", Some("with bob"), 30)),
+            ("&200,90,consulting", order(OrderKind::Income, 20000, "consulting", None, 90)),
+            ("+5,checking,savings", Some(Op::Transfer { amount: 500, source: String::from("checking"), target: String::from("savings") })),
+            (">5,tea,a,b", None),
+        ];
+        for (text, op) in cases {
+            assert_eq!(Cmd::from_str(text).unwrap().op, op, "{}", text);
+        }
+        let tr = Tr::new(Cmd::from_str(">12.50,lunch,~30,with bob #work").unwrap(), BASE_CURRENCY).unwrap();
+        assert_eq!((tr.kind, tr.tax, tr.category.as_str(), tr.duration), (OrderKind::Expense, 1250, "lunch", 30));
+        assert_eq!((tr.description.as_deref(), tr.tags), (Some("with bob"), vec![String::from("work")]));
+        assert!(Tr::new(Cmd::from_str("+5,checking,savings").unwrap(), BASE_CURRENCY).is_err());
+    }
+}
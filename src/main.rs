@@ -1,17 +1,41 @@
 use chrono::prelude::*;
-use std::str::FromStr;
-use std::{fmt, env};
-use rusqlite::{Connection, Result};
-use error::*;
+use std::{fmt, io, process};
+use std::io::IsTerminal;
+use std::path::PathBuf;
+use linda::*;
+use linda::error::*;
 
 mod cli {
     use super::*;
-    use clap::{Parser, Subcommand};
+    use clap::{CommandFactory, Parser, Subcommand};
+    use clap_complete::Shell;
 
     #[derive(Parser, Debug)]
     #[command(name = "linda")]
     #[command(author, version, about, long_about = None)]
     struct Cli {
+        /// Database file, takes precedence over LINDA_DB. Relative paths are from the current directory.
+        /// Defaults to linda/linda.db in the data directory, like ~/.local/share.
+        /// `:memory:` tries `exec` out on an empty database that isn't kept
+        #[arg(long, global = true, env = "LINDA_DB")]
+        db: Option<PathBuf>,
+        /// Use linda.db in the current directory, where it used to be by default, instead of --db or LINDA_DB
+        #[arg(long, global = true, conflicts_with = "db")]
+        local_db: bool,
+        #[arg(long, global = true, value_enum, default_value_t = Format::Text)]
+        format: Format,
+        /// Store categories exactly as typed instead of trimmed and lowercased
+        #[arg(long, global = true)]
+        exact_categories: bool,
+        /// Currency of amounts given without a code
+        #[arg(long, global = true, env = "LINDA_CURRENCY", default_value = BASE_CURRENCY, value_parser = parse_currency_arg)]
+        currency: String,
+        /// Account to book to, or to only show, instead of all of them
+        #[arg(long, global = true)]
+        account: Option<String>,
+        /// Also dump the parsed commands and rows to stderr
+        #[arg(short, long, global = true)]
+        verbose: bool,
         #[command(subcommand)]
         command: Commands,
     }
@@ -20,217 +44,665 @@ mod cli {
     enum Commands {
         #[command(arg_required_else_help = true)]
         Exec {
-            #[arg(short, long, default_value_t = String::from("&100,10,some word,other word"))]
+            /// Commands separated by `;`, or `-` to read them from stdin, one per line
+            #[arg(short, long, allow_hyphen_values = true, default_value_t = String::from("&100,10,some word,other word"))]
             text: String,
+            #[arg(short, long, default_value_t = SEP, value_parser = parse_sep)]
+            sep: char,
+            /// Show how the commands were read and the SQL they'd run, but store nothing
+            #[arg(long)]
+            dry_run: bool,
+            /// Add the accounts that don't exist yet instead of failing
+            #[arg(long)]
+            new_account: bool,
+            /// Don't look for the same order entered already
+            #[arg(long)]
+            allow_duplicate: bool,
+            /// Look for the same order within this many hours instead of on the same day
+            #[arg(long, value_name = "HOURS")]
+            duplicate_window: Option<u32>,
+            /// Only warn about orders entered already instead of asking
+            #[arg(short, long)]
+            yes: bool,
+            /// Initialize the database first if there is none
+            #[arg(long)]
+            auto_init: bool,
+        },
+        Init {
+            /// Delete the database and start over with an empty one
+            #[arg(long)]
+            force: bool,
+            /// Don't ask before deleting
+            #[arg(short, long, requires = "force")]
+            yes: bool,
+        },
+        List {
+            #[arg(short, long, default_value_t = 20)]
+            limit: u32,
+            /// First day to list, inclusive
+            #[arg(long, value_parser = parse_day_arg)]
+            since: Option<NaiveDate>,
+            /// Last day to list, inclusive
+            #[arg(long, value_parser = parse_day_arg)]
+            until: Option<NaiveDate>,
+            /// Also list deleted transactions
+            #[arg(long)]
+            include_deleted: bool,
+        },
+        /// Marks transactions as deleted, they can be restored until purged
+        Delete {
+            #[arg(required = true)]
+            ids: Vec<i64>,
+            /// Don't ask before deleting
+            #[arg(short, long)]
+            yes: bool,
+        },
+        /// Deletes the last transaction entered and shows how to enter it again
+        Undo {},
+        /// Brings back a deleted transaction
+        Restore {
+            id: i64,
+        },
+        /// Removes deleted transactions for good
+        Purge {},
+        /// Prints the net of all income, expenses and refunds
+        Balance {},
+        Report {
+            #[arg(long, value_parser = parse_day_arg)]
+            since: Option<NaiveDate>,
+            #[arg(long, value_parser = parse_day_arg)]
+            until: Option<NaiveDate>,
+        },
+        /// Lists accounts with their number of transactions and balance, or adds one
+        Accounts {
+            #[command(subcommand)]
+            action: Option<AccountAction>,
+        },
+        /// Lists categories with their number of transactions, income and expenses
+        Categories {
+            /// Sort by total, largest first, instead of by name
+            #[arg(long)]
+            by_total: bool,
+        },
+        /// Prints the number, smallest, largest and average amount of each kind of order
+        Stats {
+            #[arg(short, long)]
+            category: Option<String>,
+            #[arg(long, value_parser = parse_day_arg)]
+            since: Option<NaiveDate>,
+            #[arg(long, value_parser = parse_day_arg)]
+            until: Option<NaiveDate>,
+        },
+        Export {
+            #[arg(id = "export_format", value_enum, default_value_t = ExportFormat::Csv)]
+            format: ExportFormat,
+            #[arg(short, long)]
+            output: Option<PathBuf>,
+        },
+        /// Copies the database to a file named after the time, next to it
+        Backup {
+            /// Where to copy it instead
+            #[arg(long)]
+            to: Option<PathBuf>,
+        },
+        /// Checks the database for corruption and compacts it
+        Maintenance {},
+        /// Replaces the database with a copy made by backup
+        RestoreBackup {
+            path: PathBuf,
+            /// Also replace a database at a newer schema than the backup
+            #[arg(long)]
+            force: bool,
+            /// Don't ask before replacing
+            #[arg(short, long)]
+            yes: bool,
+        },
+        /// Inserts transactions from a CSV file in the export format
+        Import {
+            path: PathBuf,
+            /// Add the accounts that don't exist yet instead of failing
+            #[arg(long)]
+            new_account: bool,
+        },
+        /// Sets the monthly spending limit of a category
+        Budget {
+            category: String,
+            #[arg(value_parser = parse_amount)]
+            limit: i64,
+        },
+        /// Moves all transactions of a category to another one
+        RenameCategory {
+            from: String,
+            to: String,
+        },
+        /// Defines a name to stand for a command, as in `linda shorthand coffee '>1.80,coffee'`
+        Shorthand {
+            name: String,
+            template: String,
+        },
+        /// Prints a completion script, as in `linda completions bash > /etc/bash_completion.d/linda`
+        #[command(hide = true)]
+        Completions {
+            shell: Shell,
+        },
+        /// Changes the given fields of a transaction, after showing what changes
+        Edit {
+            id: i64,
+            #[arg(short, long, visible_alias = "amount", value_parser = parse_amount)]
+            tax: Option<i64>,
+            #[arg(short, long, value_parser = parse_category_arg)]
+            category: Option<String>,
+            /// An empty one removes the description
+            #[arg(long, allow_hyphen_values = true)]
+            description: Option<String>,
+            /// Like 2024-05-01, yesterday or -3d
+            #[arg(long, value_parser = parse_date_arg, allow_hyphen_values = true)]
+            date: Option<DateTime<Local>>,
+            /// income, expense or refund
+            #[arg(long, value_parser = parse_kind)]
+            kind: Option<OrderKind>,
+            /// Don't ask before changing it
+            #[arg(short, long)]
+            yes: bool,
         },
-        Init {}
     }
 
-    pub fn call() {
-        let args = Cli::parse();
-        match args.command {
-            Commands::Exec { text } => {
-                let cmd = match Cmd::from_str(&text) {
-                    Ok(c) => c,
-                    Err(e) => panic!("[error] {e:?}: {e}"),
-                };
-                run(cmd);
-            },
-            Commands::Init {} => {
-                init().expect("Can't initializing database");
-            },
+    #[derive(Debug, Subcommand)]
+    enum AccountAction {
+        /// Adds an account, to name with `@account` in a command or with --account
+        Add {
+            name: String,
+        },
+    }
+
+    fn parse_sep(text: &str) -> Result<char, String> {
+        let mut chars = text.chars();
+        match (chars.next(), chars.next()) {
+            (Some(sep), None) => Syntax::with_sep(sep).map(|_| sep).map_err(|e| e.to_string()),
+            _ => Err(String::from("the separator must be a single character")),
         }
     }
-}
 
-mod error {
-    use super::*;
+    fn parse_currency_arg(text: &str) -> Result<String, String> {
+        parse_currency(text).map_err(|e| e.describe())
+    }
 
-    #[derive(Debug)]
-    pub struct ParseCmdError;
-    #[derive(Debug)]
-    pub struct NoSpecifiedOrderKindError;
+    fn parse_day_arg(text: &str) -> Result<NaiveDate, String> {
+        parse_day(text, Local::now()).map_err(|e| e.describe())
+    }
 
-    impl fmt::Display for ParseCmdError {
-        fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-            write!(f, "The first character in the command line does not match the allowed characters")
-        }
+    fn parse_date_arg(text: &str) -> Result<DateTime<Local>, String> {
+        parse_date(text, Local::now()).map_err(|e| e.describe())
     }
 
-    impl fmt::Display for NoSpecifiedOrderKindError {
-        fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-            write!(f, "There is no operation type for the specified command")
+    fn parse_kind(text: &str) -> Result<OrderKind, String> {
+        OrderKind::from_name(text).ok_or_else(|| format!("'{}' is not a kind, expected income, expense or refund", text))
+    }
+
+    fn parse_category_arg(text: &str) -> Result<String, String> {
+        match text.trim() {
+            "" => Err(String::from("the category is empty")),
+            _ => Ok(String::from(text)),
         }
     }
-}
 
-const MODS: [char; 3] = ['&', '>', '+'];
-const SEP: char = ',';
-const DATABASE_FILENAME: &str = "linda.db";
+    fn parse_amount(text: &str) -> Result<i64, String> {
+        match parse_cents(text) {
+            Ok(Some(cents)) if cents <= 0 => Err(InvalidCmdError::InvalidAmount(cents).to_string()),
+            Ok(Some(cents)) => Ok(cents),
+            Ok(None) => Err(format!("'{}' is not an amount", text)),
+            Err(e) => Err(e.describe()),
+        }
+    }
 
-#[derive(Debug)]
-struct Cmd {
-    pack: Vec<PartOfCmdKind>,
-    created_at: DateTime<Local>
-}
+    fn fail<E: fmt::Display>(e: E) -> ! {
+        eprintln!("[error] {e}");
+        process::exit(1);
+    }
 
-#[derive(Debug)]
-enum PartOfCmdKind {
-    Mod(char),
-    Digit(i32),
-    Word(String),
-}
+    fn warn(warnings: &[String]) {
+        for warning in warnings {
+            eprintln!("[warning] {}", warning);
+        }
+    }
 
-trait FromKind {
-    fn from_kind(k: &PartOfCmdKind) -> Self;
-}
+    /// Asks a yes or no question on stderr and reads the answer from `input`,
+    /// no unless answered otherwise.
+    fn confirm(question: &str, input: &mut impl io::BufRead) -> io::Result<bool> {
+        eprint!("{} [y/N] ", question);
+        let mut answer = String::new();
+        input.read_line(&mut answer)?;
+        Ok(matches!(answer.trim().to_lowercase().as_str(), "y" | "yes"))
+    }
 
-impl FromKind for i32 {
-    fn from_kind(d: &PartOfCmdKind) -> i32 { d.unwrap_digit() }
-}
+    /// The debug dumps of what was stored when `verbose`, then a line for
+    /// each row, or its id and kind in JSON.
+    fn print_stored(stored: &Stored, verbose: bool, format: Format) {
+        if verbose {
+            for dump in &stored.debug {
+                eprintln!("[debug] {}", dump);
+            }
+        }
+        match format {
+            Format::Json => for row in &stored.rows {
+                println!("{}", serde_json::to_string(row).unwrap_or_else(|e| fail(e)));
+            },
+            Format::Text => for line in &stored.lines {
+                println!("{}", line);
+            },
+        }
+    }
 
-impl FromKind for String {
-    fn from_kind(w: &PartOfCmdKind) -> String { w.unwrap_word() }
-}
+    /// Quotes `text` for a POSIX shell.
+    fn shell_quote(text: &str) -> String {
+        format!("'{}'", text.replace('\'', r"'\''"))
+    }
 
-impl PartOfCmdKind {
-    fn unwrap_digit(&self) -> i32 {
-        match self {
-            PartOfCmdKind::Digit(d) => d.clone(),
-            _ => panic!("[error]: expected Digit, got {:?}", self)
+    /// Prints `records` as a table, income in green and expenses in red.
+    fn print_records(records: Vec<Record>, color: bool) {
+        // Deleted rows aren't colored, they don't count.
+        let kinds: Vec<Option<OrderKind>> = records.iter()
+            .map(|record| Some(record.tr.kind).filter(|_| record.deleted_at.is_none()))
+            .collect();
+        let rows: Vec<Vec<String>> = records.into_iter().map(|record| {
+            let tr = record.tr;
+            let kind = match record.deleted_at {
+                Some(_) => format!("{} (deleted)", tr.kind),
+                None => tr.kind.to_string(),
+            };
+            vec![
+                record.id.to_string(), tr.created_at.format("%Y-%m-%d %H:%M").to_string(), kind,
+                format!("{} {}", format_cents(tr.tax), tr.currency), tr.account, tr.category,
+                tr.description.unwrap_or_default(),
+            ]
+        }).collect();
+        let header = ["id", "date", "kind", "amount", "account", "category", "description"];
+        let lines = render_table(&header, &[0, 3], &rows);
+        let (head, body) = lines.split_at(2);
+        for line in head {
+            println!("{}", line);
         }
-    }
-    fn unwrap_word(&self) -> String {
-        match self {
-            PartOfCmdKind::Word(w) => w.clone(),
-            _ => panic!("[error]: expected Word, got {:?}", self)
+        for (line, kind) in body.iter().zip(kinds) {
+            match kind {
+                Some(OrderKind::Income) => println!("{}", paint(line, Style::Green, color)),
+                Some(OrderKind::Expense) => println!("{}", paint(line, Style::Red, color)),
+                _ => println!("{}", line),
+            }
         }
     }
-    fn unwrap<T: FromKind>(&self) -> T { T::from_kind(self) }
-}
 
-#[derive(Debug)]
-enum OrderKind {
-    Income,
-    Expense,
-}
-
-#[derive(Debug)]
-enum CmdKind {
-    Order(OrderKind),
-}
+    /// The completion script for `shell`.
+    fn completion_script(shell: Shell) -> Vec<u8> {
+        let mut script = Vec::new();
+        clap_complete::generate(shell, &mut Cli::command(), "linda", &mut script);
+        script
+    }
 
-impl fmt::Display for PartOfCmdKind {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        match self {
-            PartOfCmdKind::Mod(ch) => write!(f, "{}", ch),
-            PartOfCmdKind::Digit(d) => write!(f, "{}", d),
-            PartOfCmdKind::Word(w) => write!(f, "{}", w)
+    /// Writes the completion script for `shell` to stdout. A closed pipe, as
+    /// with `| head`, is not an error.
+    fn print_completions(shell: Shell) {
+        use std::io::Write;
+        match io::stdout().lock().write_all(&completion_script(shell)) {
+            Err(e) if e.kind() != io::ErrorKind::BrokenPipe => fail(e),
+            _ => {},
         }
     }
-}
 
-impl OrderKind {
-    fn new(ch: char) -> Result<OrderKind, NoSpecifiedOrderKindError> {
-        match ch {
-            '&' => Ok(OrderKind::Income),
-            '>' => Ok(OrderKind::Expense),
-            _ => Err(NoSpecifiedOrderKindError)
+    pub fn call() {
+        let mut args = Cli::parse();
+        // Nothing to do with a database.
+        if let Commands::Completions { shell } = args.command {
+            print_completions(shell);
+            return;
+        }
+        let given = if args.local_db { Some(PathBuf::from(DATABASE_FILENAME)) } else { args.db.take() };
+        let (db, warning) = resolve_db(given).unwrap_or_else(|e| fail(e));
+        warn(warning.as_slice());
+        let memory = db.as_os_str() == MEMORY_DB;
+        if memory && !matches!(args.command, Commands::Exec { .. }) {
+            fail(format!("{} only works with exec, there'd be nothing in it", MEMORY_DB));
+        }
+        let syntax = Syntax { normalize: !args.exact_categories, ..Syntax::default() };
+        let color = use_color();
+        match args.command {
+            Commands::Exec { text, sep, dry_run, new_account, allow_duplicate, duplicate_window, yes, auto_init } => {
+                if auto_init && !memory && !db.exists() {
+                    init(&db, &args.currency, false).unwrap_or_else(|e| fail(e));
+                    println!("Created {} at schema version {}", db.display(), SCHEMA_VERSION);
+                }
+                let syntax = Syntax {
+                    sep: Syntax::with_sep(sep).unwrap_or_else(|e| fail(e)).sep,
+                    shorthands: shorthands(&db).unwrap_or_else(|e| fail(e)),
+                    ..syntax
+                };
+                let mut cmds = if text == "-" {
+                    let input = io::read_to_string(io::stdin()).unwrap_or_else(|e| fail(e));
+                    parse_lines(&input, &syntax)
+                } else {
+                    parse_batch(&text, &syntax)
+                }.unwrap_or_else(|e| fail(e));
+                for cmd in cmds.iter_mut().filter(|cmd| cmd.account.is_none()) {
+                    cmd.account.clone_from(&args.account);
+                }
+                if dry_run {
+                    for line in linda::dry_run(&cmds, &args.currency).unwrap_or_else(|e| fail(e)) {
+                        println!("{}", line);
+                    }
+                } else if memory {
+                    let (stored, records) = run_in_memory(&cmds, &args.currency, new_account).unwrap_or_else(|e| fail(e));
+                    print_stored(&stored, args.verbose, Format::Text);
+                    println!("Would have inserted {} row(s)", stored.lines.len());
+                    print_records(records, color);
+                } else {
+                    if !allow_duplicate {
+                        let found = duplicates(&db, &cmds, &args.currency, duplicate_window).unwrap_or_else(|e| fail(e));
+                        let lines: Vec<String> = found.iter().map(|d| format!(
+                            "{} looks like #{}, entered for {}", d.text, d.id, d.created_at.format("%Y-%m-%d %H:%M")
+                        )).collect();
+                        warn(&lines);
+                        // Batches and piped input go in with the warning rather than wait on a prompt.
+                        let ask = !yes && text != "-" && cmds.len() == 1 && io::stdin().is_terminal();
+                        if ask && !found.is_empty() && !confirm("Insert anyway?", &mut io::stdin().lock()).unwrap_or_else(|e| fail(e)) {
+                            println!("Inserted nothing");
+                            return;
+                        }
+                    }
+                    let stored = run(&db, &cmds, &args.currency, new_account).unwrap_or_else(|e| fail(e));
+                    print_stored(&stored, args.verbose, args.format);
+                    if let Format::Text = args.format {
+                        println!("Inserted {} row(s)", stored.lines.len());
+                    }
+                    warn(&stored.warnings);
+                }
+            },
+            Commands::Init { force, yes } => {
+                if force && db.exists() {
+                    let count = count_transactions(&db).unwrap_or(0);
+                    let question = format!("This will delete {} existing transaction(s) in {}, continue?", count, db.display());
+                    if !yes && !confirm(&question, &mut io::stdin().lock()).unwrap_or_else(|e| fail(e)) {
+                        println!("Left {} as it was", db.display());
+                        return;
+                    }
+                }
+                match init(&db, &args.currency, force).unwrap_or_else(|e| fail(e)) {
+                    None => println!("Created {} at schema version {}", db.display(), SCHEMA_VERSION),
+                    Some(SCHEMA_VERSION) => println!(
+                        "Database already initialized at {} ({} transactions)",
+                        db.display(), count_transactions(&db).unwrap_or_else(|e| fail(e))
+                    ),
+                    Some(from) => println!(
+                        "Migrated the schema of {} from version {} to {}, the old one is kept in {}",
+                        db.display(), from, SCHEMA_VERSION, backup_path(&db, from).display()
+                    ),
+                }
+            },
+            Commands::List { limit, since, until, include_deleted } => {
+                let records = list(&db, Period::new(since, until), args.account.as_deref(), limit, include_deleted)
+                    .unwrap_or_else(|e| fail(e));
+                if let Format::Json = args.format {
+                    println!("{}", serde_json::to_string_pretty(&records).unwrap_or_else(|e| fail(e)));
+                    return;
+                }
+                print_records(records, color);
+            },
+            Commands::Delete { ids, yes } => {
+                let records = to_delete(&db, &ids).unwrap_or_else(|e| fail(e));
+                for record in &records {
+                    println!("{}", record.tr.describe(record.id));
+                }
+                let question = format!("Delete {} transaction(s)?", records.len());
+                if !yes && !confirm(&question, &mut io::stdin().lock()).unwrap_or_else(|e| fail(e)) {
+                    println!("Deleted nothing");
+                    return;
+                }
+                delete(&db, &ids).unwrap_or_else(|e| fail(e));
+                match records.as_slice() {
+                    [record] => println!("Deleted transaction #{}, `linda restore {}` brings it back", record.id, record.id),
+                    _ => println!("Deleted {} transactions, `linda restore <id>` brings one back", records.len()),
+                }
+            },
+            Commands::Undo {} => {
+                let undone = undo(&db).unwrap_or_else(|e| fail(e));
+                for line in &undone.lines {
+                    println!("Undid {}", line);
+                }
+                let account = match &undone.account {
+                    Some(account) => format!("--account {} ", shell_quote(account)),
+                    None => String::new(),
+                };
+                println!("To enter it again: linda {}exec --text {}", account, shell_quote(&undone.text));
+            },
+            Commands::Restore { id } => {
+                restore(&db, id).unwrap_or_else(|e| fail(e));
+                println!("Restored transaction #{}", id);
+            },
+            Commands::Purge {} => {
+                let purged = purge(&db).unwrap_or_else(|e| fail(e));
+                println!("Purged {} deleted transaction(s)", purged);
+            },
+            Commands::Balance {} => {
+                let balance = balance(&db, args.account.as_deref()).unwrap_or_else(|e| fail(e));
+                warn(&balance.warnings);
+                match args.format {
+                    Format::Json => println!("{}", serde_json::to_string(&balance).unwrap_or_else(|e| fail(e))),
+                    Format::Text => println!("{}", paint(&format_cents(balance.balance), Style::Bold, color)),
+                }
+            },
+            Commands::Report { since, until } => {
+                let totals = report(&db, Period::new(since, until), args.account.as_deref(), &args.currency)
+                    .unwrap_or_else(|e| fail(e));
+                match args.format {
+                    Format::Json => println!("{}", serde_json::to_string_pretty(&totals).unwrap_or_else(|e| fail(e))),
+                    Format::Text => for t in totals {
+                        println!("income\t{} {}", format_cents(t.income), t.currency);
+                        println!("expense\t{} {}", format_cents(t.expense), t.currency);
+                        println!("{}", paint(&format!("net\t{} {}", format_cents(t.net), t.currency), Style::Bold, color));
+                    },
+                }
+            },
+            Commands::Accounts { action: None } => {
+                let accounts = accounts(&db).unwrap_or_else(|e| fail(e));
+                match args.format {
+                    Format::Json => println!("{}", serde_json::to_string_pretty(&accounts).unwrap_or_else(|e| fail(e))),
+                    Format::Text => for row in accounts {
+                        println!("{}\t{}\t{}", row.account, row.count, format_cents(row.balance));
+                    },
+                }
+            },
+            Commands::Accounts { action: Some(AccountAction::Add { name }) } => {
+                match new_account(&db, &name).unwrap_or_else(|e| fail(e)) {
+                    true => println!("Added account '{}'", name),
+                    false => println!("There already is an account named '{}'", name),
+                }
+            },
+            Commands::Categories { by_total } => {
+                let categories = categories(&db, by_total).unwrap_or_else(|e| fail(e));
+                warn(&categories.warnings);
+                match args.format {
+                    Format::Json => println!("{}", serde_json::to_string_pretty(&categories).unwrap_or_else(|e| fail(e))),
+                    Format::Text => for row in categories.rows {
+                        println!("{}\t{}\t{}\t{}", row.category, row.count, format_cents(row.income), format_cents(row.expense));
+                    },
+                }
+            },
+            Commands::Stats { category, since, until } => {
+                let category = category.map(|c| syntax.category(&c));
+                let stats = stats(&db, category.as_deref(), Period::new(since, until), args.account.as_deref())
+                    .unwrap_or_else(|e| fail(e));
+                match args.format {
+                    Format::Json => println!("{}", serde_json::to_string_pretty(&stats).unwrap_or_else(|e| fail(e))),
+                    Format::Text if stats.is_empty() => println!("no data"),
+                    Format::Text => {
+                        let rows: Vec<Vec<String>> = stats.iter().map(|s| vec![
+                            s.kind.to_string(), s.currency.clone(), s.count.to_string(),
+                            format_cents(s.min), format_cents(s.max), format_cents(s.average),
+                        ]).collect();
+                        let header = ["kind", "currency", "count", "min", "max", "average"];
+                        for line in render_table(&header, &[2, 3, 4, 5], &rows) {
+                            println!("{}", line);
+                        }
+                    },
+                }
+            },
+            Commands::Export { format, output } => {
+                export(&db, format, output.as_deref()).unwrap_or_else(|e| fail(e));
+            },
+            Commands::Backup { to } => {
+                let (to, count) = backup(&db, to.as_deref()).unwrap_or_else(|e| fail(e));
+                println!("Backed up {} transaction(s) to {}", count, to.display());
+            },
+            Commands::Maintenance {} => {
+                let done = maintenance(&db).unwrap_or_else(|e| fail(e));
+                println!("Integrity check passed");
+                println!("Size went from {} to {} bytes", done.before, done.after);
+                for (table, count) in done.tables {
+                    println!("{}\t{}", table, count);
+                }
+            },
+            Commands::RestoreBackup { path, force, yes } => {
+                let (backup, current) = check_backup(&db, &path, force).unwrap_or_else(|e| fail(e));
+                let question = match &current {
+                    Some(Ok(current)) => format!("The backup has {}, the database has {}. Replace it?", backup, current),
+                    Some(Err(e)) => format!("The backup has {}, the database can't be read ({}). Replace it?", backup, e),
+                    None => format!("The backup has {}. Restore it to {}?", backup, db.display()),
+                };
+                if !yes && !confirm(&question, &mut io::stdin().lock()).unwrap_or_else(|e| fail(e)) {
+                    println!("Left {} as it was", db.display());
+                    return;
+                }
+                restore_backup(&db, &path, force).unwrap_or_else(|e| fail(e));
+                println!("Restored {} from {}", db.display(), path.display());
+            },
+            Commands::Import { path, new_account } => {
+                let count = import(&db, &path, &syntax, new_account).unwrap_or_else(|e| fail(e));
+                println!("Imported {} row(s)", count);
+            },
+            Commands::Budget { category, limit } => {
+                let category = syntax.category(&category);
+                budget(&db, &category, limit).unwrap_or_else(|e| fail(e));
+                println!("Budget for {} set to {} a month", category, format_cents(limit));
+            },
+            Commands::RenameCategory { from, to } => {
+                let (from, to) = (syntax.category(&from), syntax.category(&to));
+                let renamed = rename_category(&db, &from, &to).unwrap_or_else(|e| fail(e));
+                println!("Renamed {} transaction(s) from {} to {}", renamed, from, to);
+            },
+            Commands::Shorthand { name, template } => {
+                let template = shorthand(&db, &name, &template, &syntax).unwrap_or_else(|e| fail(e));
+                println!("Shorthand {} expands to {}", name.to_lowercase(), template);
+            },
+            Commands::Edit { id, tax, category, description, date, kind, yes } => {
+                let changes = Changes {
+                    kind,
+                    tax,
+                    category: category.map(|c| syntax.category(&c)),
+                    description,
+                    created_at: date,
+                };
+                let diff = edit_diff(&db, id, &changes).unwrap_or_else(|e| fail(e));
+                if diff.is_empty() {
+                    println!("Transaction #{} is like that already", id);
+                    return;
+                }
+                for line in &diff {
+                    println!("{}", line);
+                }
+                let question = format!("Update transaction #{}?", id);
+                if !yes && !confirm(&question, &mut io::stdin().lock()).unwrap_or_else(|e| fail(e)) {
+                    println!("Left transaction #{} as it was", id);
+                    return;
+                }
+                let record = edit(&db, id, &changes).unwrap_or_else(|e| fail(e));
+                print_records(vec![record], color);
+            },
+            Commands::Completions { .. } => unreachable!("handled before the database is resolved"),
         }
     }
-}
 
-#[derive(Debug)]
-struct Tr {
-    created_at: DateTime<Local>,
-    tax: i32,
-    category: String
-}
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+        use std::env;
 
-impl Tr {
-    fn new(cmd: Cmd) -> Option<Tr> {
-        match cmd.kind_of() {
-            Some(CmdKind::Order(OrderKind::Income)) =>
-                Some(Tr {
-                    created_at: cmd.created_at,
-                    tax: cmd.pack[1].unwrap(),
-                    category: cmd.pack[2].unwrap(),
-                }),
-            _ => None
+        #[test]
+        fn confirm_defaults_to_no() {
+            let answer = |text: &str| confirm("Go?", &mut io::Cursor::new(text)).unwrap();
+            assert!(answer("y\n") && answer("YES\n") && answer(" yes "));
+            assert!(!answer("\n") && !answer("n\n") && !answer("yeah\n") && !answer(""));
+            let args = Cli::try_parse_from(["linda", "init", "--force", "-y"]).unwrap();
+            assert!(matches!(args.command, Commands::Init { force: true, yes: true }));
+            assert!(Cli::try_parse_from(["linda", "init", "--yes"]).is_err(), "--yes needs --force");
         }
-    }
-}
 
-impl Cmd {
-    fn to_sql(&self) -> Option<String> {
-        match self.kind_of() {
-            Some(CmdKind::Order(OrderKind::Income)) => format!(
-                "INSERT INTO transaction (created_at, tax, category) VALUES ({}, {}, '{}')", self.created_at, self.pack[1], self.pack[2]
-                ).into(),
-            _ => None
+        #[test]
+        fn db_comes_from_flag_then_env() {
+            let from_env = env::temp_dir().join(format!("linda-env-{}.db", process::id()));
+            let _ = std::fs::remove_file(&from_env);
+            env::set_var("LINDA_DB", &from_env);
+            let args = Cli::try_parse_from(["linda", "init"]).unwrap();
+            assert_eq!(args.db.as_ref(), Some(&from_env));
+            let flagged = Cli::try_parse_from(["linda", "--db", "other.db", "list"]).unwrap();
+            assert_eq!(flagged.db, Some(PathBuf::from("other.db")));
+            env::remove_var("LINDA_DB");
+            assert!(Cli::try_parse_from(["linda", "--local-db", "list"]).unwrap().local_db);
+            assert!(Cli::try_parse_from(["linda", "--db", "other.db", "--local-db", "list"]).is_err());
+
+            init(&from_env, BASE_CURRENCY, false).unwrap();
+            run(&from_env, &parse_batch(">120,coffee", &Syntax::default()).unwrap(), BASE_CURRENCY, false).unwrap();
+            assert_eq!(list(&from_env, Period::new(None, None), None, 10, false).unwrap().len(), 1);
+            std::fs::remove_file(&from_env).unwrap();
         }
-    }
-    fn kind_of(&self) -> Option<CmdKind> {
-        match self.pack[..] {
-            [
-                PartOfCmdKind::Mod(ch),
-                PartOfCmdKind::Digit(_),
-                PartOfCmdKind::Word(_),
-            ] => Some(CmdKind::Order(OrderKind::new(ch).unwrap_or_else( |e| {
-                panic!("[error] {e:?}: {e}");
-            }))),
-            _ => None,
+
+        #[test]
+        fn completions_cover_each_shell() {
+            for shell in [Shell::Bash, Shell::Zsh, Shell::Fish] {
+                let args = Cli::try_parse_from(["linda", "completions", &shell.to_string()]).unwrap();
+                assert!(matches!(args.command, Commands::Completions { shell: s } if s == shell));
+                let script = String::from_utf8(completion_script(shell)).unwrap();
+                assert!(script.contains("restore-backup") && script.contains("exact-categories"), "{}: {}", shell, script);
+            }
+            assert!(Cli::try_parse_from(["linda", "completions", "tcsh"]).is_err());
         }
-    }
-}
 
-impl FromStr for Cmd {
-    type Err = ParseCmdError;
+        #[test]
+        fn verbose_goes_anywhere() {
+            assert!(Cli::try_parse_from(["linda", "-v", "list"]).unwrap().verbose);
+            assert!(Cli::try_parse_from(["linda", "exec", "-t", ">5,tea", "--verbose"]).unwrap().verbose);
+            assert!(!Cli::try_parse_from(["linda", "list"]).unwrap().verbose);
+        }
 
-    fn from_str(text: &str) -> Result<Self, Self::Err> {
-        let created_at = Local::now();
-        let mut chars = text.chars();
-        let ch = chars.next().unwrap();
-        if !MODS.contains(&ch) { return Err(ParseCmdError); }
-        let mut pack: Vec<PartOfCmdKind> = vec![
-            PartOfCmdKind::Mod(ch)
-        ];
-
-        for mut ch in chars.as_str().split(SEP) {
-            ch = ch.trim();
-            if let Ok(d) = ch.parse::<i32>() {
-                pack.push(PartOfCmdKind::Digit(d))
-            } else {
-                pack.push(PartOfCmdKind::Word(String::from(ch)))
+        #[test]
+        fn refunds_start_with_a_hyphen() {
+            for args in [["linda", "exec", "-t", "-"], ["linda", "exec", "--text", "-"]] {
+                match Cli::try_parse_from(args).unwrap().command {
+                    Commands::Exec { text, .. } => assert_eq!(text, "-", "stdin"),
+                    other => panic!("{:?}", other),
+                }
+            }
+            for args in [["linda", "exec", "-t", "-800,electronics"], ["linda", "exec", "--text", "-800,electronics"]] {
+                match Cli::try_parse_from(args).unwrap().command {
+                    Commands::Exec { text, .. } => assert_eq!(text, "-800,electronics"),
+                    other => panic!("{:?}", other),
+                }
             }
         }
-        println!("Cmd {:?} created at {}", pack, created_at);
-        Ok(Cmd { pack, created_at})
-    }
-}
-
-fn init() -> Result<(), Box<dyn std::error::Error>> {
-    let conn = Connection::open(DATABASE_FILENAME)?;
-    conn.execute(
-        "CREATE TABLE IF NOT EXISTS `transaction` (
-          id INTEGER PRIMARY KEY AUTOINCREMENT,
-          created_at INTEGER NOT NULL,
-          tax INTEGER NOT NULL,
-          category TEXT NOT NULL,
-          duration INTEGER DEFAULT 0,
-          description TEXT
-        )",
-        (), // empty list of parameters.
-    )?;
-    Ok(())
-}
 
-fn run(cmd: Cmd) {
-    println!("{:?}", cmd.to_sql().unwrap());
-    let transaction = Tr::new(cmd).unwrap();
-    println!("{:?}", transaction);
-    println!("{:?}, {}, {}", transaction.created_at, transaction.tax, transaction.category);
-    println!("{:?}", env::current_dir());
+        #[test]
+        fn edit_takes_amount_kind_and_description() {
+            let args = ["linda", "edit", "3", "--amount", "3.50", "--kind", "refund", "--description", "", "--date", "yesterday"];
+            match Cli::try_parse_from(args).unwrap().command {
+                Commands::Edit { id, tax, kind, description, date, category, yes } => {
+                    assert_eq!((id, tax, kind, description.as_deref()), (3, Some(350), Some(OrderKind::Refund), Some("")));
+                    assert_eq!(date.map(|date| date.date_naive()), Local::now().date_naive().pred_opt());
+                    assert!(category.is_none() && !yes);
+                },
+                other => panic!("{:?}", other),
+            }
+            for bad in [["--kind", "gift"], ["--category", " "], ["--amount", "0"], ["--date", "someday"]] {
+                assert!(Cli::try_parse_from(["linda", "edit", "3", bad[0], bad[1]]).is_err(), "{:?}", bad);
+            }
+        }
+    }
 }
 
 fn main() {
@@ -0,0 +1,26 @@
+//! Uses linda the way another crate would, through its public API only.
+
+use linda::*;
+
+#[test]
+fn parses_into_orders() {
+    let cmd: Cmd = ">250,groceries,weekly shop".parse().unwrap();
+    let tr = Tr::new(cmd, BASE_CURRENCY).unwrap();
+    assert!(matches!(tr.kind, OrderKind::Expense));
+    assert_eq!((tr.tax, tr.category.as_str()), (25000, "groceries"));
+    assert_eq!(tr.description.as_deref(), Some("weekly shop"));
+}
+
+#[test]
+fn stores_and_reads_back() {
+    let db = std::env::temp_dir().join(format!("linda-library-{}.db", std::process::id()));
+    let _ = std::fs::remove_file(&db);
+    init(&db, BASE_CURRENCY, false).unwrap();
+    let cmds = parse_batch("&100,work;>12.50,lunch", &Syntax::default()).unwrap();
+    assert_eq!(run(&db, &cmds, BASE_CURRENCY, false).unwrap().lines.len(), 2);
+    let records = list(&db, Period::new(None, None), None, 10, false).unwrap();
+    assert_eq!(records.iter().map(|r| r.tr.tax).collect::<Vec<_>>(), [1250, 10000]);
+    let totals = &report(&db, Period::new(None, None), None, BASE_CURRENCY).unwrap()[0];
+    assert_eq!((totals.income, totals.expense, totals.net), (10000, 1250, 8750));
+    std::fs::remove_file(&db).unwrap();
+}